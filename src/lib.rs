@@ -0,0 +1,5794 @@
+/*!
+ * @title Staking Contract
+ * @author Bless Hukporti
+ * @notice This contract enables users to stake tokens and earn rewards based on their stake proportion.
+ * @dev The contract is designed to handle staking operations with a maximum duration of  7 days from deployment
+ *      by default; the window can be configured per deployment.
+ *      It uses a BTreeMap, keyed by username, to track stakes and calculates rewards upon distribution.
+ *      The core builds under `no_std` with `alloc`; the default `std` feature adds the system clock,
+ *      `MockClock` and `SharedContract`.
+ */
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use chrono::{DateTime, Duration, Utc};
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+#[cfg(feature = "std")]
+use std::sync::{Mutex, MutexGuard};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StakingError {
+    WindowClosed,
+    InsufficientStake,
+    UnknownStaker,
+    BelowMinimum,
+    ZeroAmount,
+    CapExceeded,
+    Finalized,
+    StillLocked,
+    InvalidUser,
+    Paused,
+    TooFewStakers,
+    Locked,
+    NotWhitelisted,
+    CooldownActive,
+    InvalidRoster(String),
+    EmptyPool,
+    Unauthorized,
+    InvalidBytes(String),
+    InsufficientPool,
+    NotPaused,
+    Frozen,
+    InvalidDuration,
+}
+
+impl fmt::Display for StakingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StakingError::WindowClosed => {
+                write!(f, "Cannot stake after the staking window has closed")
+            }
+            StakingError::InsufficientStake => write!(f, "Insufficient stake"),
+            StakingError::UnknownStaker => write!(f, "Unknown staker"),
+            StakingError::BelowMinimum => write!(f, "Stake is below the minimum amount"),
+            StakingError::ZeroAmount => write!(f, "Cannot stake a zero amount"),
+            StakingError::CapExceeded => write!(f, "Stake would exceed the pool cap"),
+            StakingError::Finalized => write!(f, "Contract has been finalized"),
+            StakingError::StillLocked => write!(f, "Stake is still locked"),
+            StakingError::InvalidUser => write!(f, "Username must not be empty"),
+            StakingError::Paused => write!(f, "Staking is paused"),
+            StakingError::TooFewStakers => write!(f, "Not enough stakers to distribute"),
+            StakingError::Locked => write!(f, "Contract lock is poisoned"),
+            StakingError::NotWhitelisted => write!(f, "User is not whitelisted"),
+            StakingError::CooldownActive => {
+                write!(f, "Cannot stake again until the cooldown has passed")
+            }
+            StakingError::InvalidRoster(reason) => write!(f, "Invalid roster: {}", reason),
+            StakingError::EmptyPool => write!(f, "Reward pool is empty"),
+            StakingError::Unauthorized => write!(f, "Caller is not the contract owner"),
+            StakingError::InvalidBytes(reason) => write!(f, "Invalid contract bytes: {}", reason),
+            StakingError::InsufficientPool => {
+                write!(f, "Reward pool cannot cover the minimum reward")
+            }
+            StakingError::NotPaused => write!(f, "Contract is not paused"),
+            StakingError::Frozen => write!(f, "Staker is frozen"),
+            StakingError::InvalidDuration => write!(f, "Duration must be positive"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for StakingError {}
+
+/// Source of the current time, injected so the staking window can be tested.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Reads the real system time.
+#[cfg(feature = "std")]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A manually driven clock; clones share the same time.
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<DateTime<Utc>>>,
+}
+
+#[cfg(feature = "std")]
+impl MockClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        MockClock {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// A staker's position and when it was (on average) deposited.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StakeEntry {
+    pub amount: u64,
+    pub staked_at: DateTime<Utc>,
+    /// Lockup chosen via `stake_locked`; it applies to the whole position.
+    #[serde(default, with = "duration_millis")]
+    pub lock: Duration,
+    #[serde(default)]
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+impl StakeEntry {
+    fn deposit(amount: u64, at: DateTime<Utc>) -> Self {
+        StakeEntry {
+            amount,
+            staked_at: at,
+            lock: Duration::zero(),
+            locked_until: None,
+        }
+    }
+}
+
+/// A point-in-time copy of a contract's pool, detached from the live contract.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub total_coins: u128,
+    pub stakers: BTreeMap<String, StakeEntry>,
+    pub start_date: DateTime<Utc>,
+    pub total_staked: u64,
+}
+
+/// A distribution together with how much of the pool it paid out.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DistributionResult {
+    pub rewards: Vec<(String, u128)>,
+    pub total_distributed: u128,
+    /// Part of `total_coins` left unpaid, e.g. because every staker is
+    /// capped.
+    pub leftover: u128,
+    pub at: DateTime<Utc>,
+}
+
+/// One staker's position and projected reward, as exported by
+/// `Contract::reward_schedule`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RewardRow {
+    pub user: String,
+    pub stake: u64,
+    pub share_bps: u16,
+    pub reward: u128,
+}
+
+/// Which weighting `distribute_rewards` uses; each mode matches one of the
+/// `distribute_*` methods.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DistributionMode {
+    #[default]
+    Proportional,
+    TimeWeighted,
+    Prorated,
+    SquareRoot,
+    Tiered,
+    Lockup,
+    ReferralBonus,
+}
+
+/// What happens to `total_coins` when stake is withdrawn with `unstake`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PoolPolicy {
+    /// The pool stays the same, so the remaining stakers' shares grow.
+    #[default]
+    Fixed,
+    /// The pool shrinks by the fraction of the total stake withdrawn.
+    Burn,
+}
+
+/// Decides how much weight each staker carries in a distribution.
+pub trait DistributionStrategy: Send + Sync {
+    fn weights(&self, contract: &Contract) -> Vec<(String, u128)>;
+}
+
+/// Time-based modes measure up to the current time, or the end of the window
+/// for `Prorated`.
+impl DistributionStrategy for DistributionMode {
+    fn weights(&self, contract: &Contract) -> Vec<(String, u128)> {
+        match self {
+            DistributionMode::Proportional => contract.stake_weights(),
+            DistributionMode::TimeWeighted => contract.time_weighted_weights(contract.clock.now()),
+            DistributionMode::Prorated => contract.prorated_weights(contract.deadline()),
+            DistributionMode::SquareRoot => contract.sqrt_weights(),
+            DistributionMode::Tiered => contract.tiered_weights(),
+            DistributionMode::Lockup => contract.lockup_weights(),
+            DistributionMode::ReferralBonus => contract.referral_weights(),
+        }
+    }
+}
+
+/// How a staker's proportional share is rounded to whole tokens.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RoundingMode {
+    #[default]
+    Floor,
+    Nearest,
+}
+
+/// How stakers with equal weight are ordered when one of them must be
+/// picked, e.g. to receive rounding dust.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TieBreak {
+    /// Alphabetically first username wins.
+    #[default]
+    ByName,
+    /// Order by a hash of the seed and username, so the winner is arbitrary
+    /// but reproducible.
+    Seeded(u64),
+}
+
+impl TieBreak {
+    /// Orders `a` before `b` if `a` wins the tie.
+    fn order(&self, a: &str, b: &str) -> core::cmp::Ordering {
+        match self {
+            TieBreak::ByName => a.cmp(b),
+            TieBreak::Seeded(seed) => {
+                let key = |user: &str| {
+                    let mut hasher = Sha256::new();
+                    hasher.update(seed.to_le_bytes());
+                    hasher.update(user.as_bytes());
+                    <[u8; 32]>::from(hasher.finalize())
+                };
+                key(a).cmp(&key(b)).then_with(|| a.cmp(b))
+            }
+        }
+    }
+}
+
+/// Where a contract is in its lifecycle, as reported by `Contract::state`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContractState {
+    Open,
+    Paused,
+    Closed,
+    Finalized,
+}
+
+/// Something that happened to the pool, recorded in `Contract::events`.
+/// Every change to a stake is logged, including slashes, sweeps, removals,
+/// transfers and merges.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Event {
+    Staked {
+        user: String,
+        amount: u64,
+        at: DateTime<Utc>,
+    },
+    Unstaked {
+        user: String,
+        amount: u64,
+        at: DateTime<Utc>,
+    },
+    Distributed {
+        total: u128,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Contract {
+    /// Version of the serialized format; see `FORMAT_VERSION`.
+    #[serde(default)]
+    format_version: u32,
+    pub total_coins: u128,
+    pub stakers: BTreeMap<String, StakeEntry>,
+    pub start_date: DateTime<Utc>,
+    #[serde(with = "duration_millis")]
+    pub staking_duration: Duration,
+    pub min_stake: u64,
+    #[serde(default)]
+    pub max_total_stake: Option<u64>,
+    /// Share of an early unstake kept back; values above 10000 bps count as
+    /// 10000.
+    #[serde(default)]
+    pub early_withdraw_penalty_bps: u16,
+    #[serde(default)]
+    pub min_stakers_for_distribution: usize,
+    /// `(threshold, multiplier_bps)` pairs used by `distribute_tiered_rewards`.
+    #[serde(default)]
+    pub reward_tiers: Vec<(u64, u16)>,
+    /// Whether usernames are lowercased, so "Alice" and "alice" are the same
+    /// staker in every call. Stored keys are then lowercase.
+    #[serde(default)]
+    pub normalize_usernames: bool,
+    /// Users allowed to stake; `None` leaves staking open to everyone.
+    #[serde(default)]
+    pub whitelist: Option<BTreeSet<String>>,
+    /// Weight bonus per active referral under `distribute_with_referral_bonus`.
+    #[serde(default)]
+    pub referral_bonus_bps: u16,
+    /// Referred user -> referrer.
+    #[serde(default)]
+    pub referred_by: BTreeMap<String, String>,
+    /// Beneficiary -> the delegator who funded their stake.
+    #[serde(default)]
+    delegations: BTreeMap<String, String>,
+    /// Users who cannot unstake, withdraw, claim or transfer until unfrozen.
+    #[serde(default)]
+    frozen: BTreeSet<String>,
+    /// Fee skimmed from every deposit into `fee_pool`; values above 10000
+    /// bps count as 10000.
+    #[serde(default)]
+    pub stake_fee_bps: u16,
+    #[serde(default)]
+    pub fee_pool: u64,
+    /// Whether slashed stake is added to `total_coins` instead of burned.
+    #[serde(default)]
+    pub slash_to_pool: bool,
+    #[serde(default)]
+    pub pool_policy: PoolPolicy,
+    /// Whether stake removed by `sweep_dust` is added to `total_coins`.
+    #[serde(default)]
+    pub sweep_to_pool: bool,
+    #[serde(default)]
+    pub unclaimed: BTreeMap<String, u128>,
+    /// The epoch whose rewards `accrue_rewards` has already credited.
+    #[serde(default)]
+    accrued_epoch: Option<u64>,
+    #[serde(default)]
+    pub epoch: u64,
+    /// Distribution recorded for each completed epoch, indexed by epoch.
+    #[serde(default)]
+    epoch_history: Vec<Vec<(String, u128)>>,
+    /// Sum of every distribution committed so far; see `lifetime_distributed`.
+    #[serde(default)]
+    lifetime_distributed: u128,
+    /// The epoch whose distribution `lifetime_distributed` already includes.
+    #[serde(default)]
+    counted_epoch: Option<u64>,
+    #[serde(default)]
+    paused: bool,
+    #[serde(default)]
+    finalized: bool,
+    #[serde(default)]
+    final_rewards: Vec<(String, u128)>,
+    #[serde(default)]
+    distributed_at: Option<DateTime<Utc>>,
+    #[serde(default, with = "duration_millis")]
+    pub vesting_duration: Duration,
+    #[serde(default, with = "duration_millis")]
+    pub vesting_cliff: Duration,
+    /// Account allowed to pause, finalize, slash, adjust stakes, withdraw
+    /// fees, advance epochs, extend the window, sweep dust and release the
+    /// pool by fraction. A contract without an owner refuses those
+    /// operations.
+    #[serde(default)]
+    owner: String,
+    #[serde(default)]
+    events: Vec<Event>,
+    /// Minimum time between two stakes by the same user.
+    #[serde(default, with = "duration_millis")]
+    pub stake_cooldown: Duration,
+    #[serde(default)]
+    last_stake_at: BTreeMap<String, DateTime<Utc>>,
+    /// The deposits making up each user's stake, oldest first.
+    #[serde(default)]
+    stake_history: BTreeMap<String, Vec<(u64, DateTime<Utc>)>>,
+    /// How long after the window closes existing stakers may still top up.
+    #[serde(default, with = "duration_millis")]
+    pub grace_period: Duration,
+    #[serde(default)]
+    pub distribution_mode: DistributionMode,
+    /// Whether `distribute_rewards` finalizes the contract once the window
+    /// has closed.
+    #[serde(default)]
+    pub auto_finalize: bool,
+    #[serde(default)]
+    pub rounding_mode: RoundingMode,
+    #[serde(default)]
+    pub tie_break: TieBreak,
+    /// Resolution at which the fractions lost to rounding are compared when
+    /// handing out dust, e.g. `1_000_000` for millionths of a token. Above 1,
+    /// dust left over after rounding down goes one token at a time to the
+    /// largest fractions instead of all to the heaviest staker, so a small
+    /// staker just short of a whole token receives it; fractions equal at
+    /// this resolution go heaviest first. Nearest rounding always settles by
+    /// fraction, exactly unless a scale is set. 0 (the default) and 1 leave
+    /// the scale off.
+    #[serde(default)]
+    pub precision_scale: u128,
+    /// Additional reward tokens paid out by `distribute_multi`, token -> pool.
+    #[serde(default)]
+    pub reward_pools: BTreeMap<String, u128>,
+    /// Largest reward `distribute_rewards` pays any one staker.
+    #[serde(default)]
+    pub max_reward_per_user: Option<u128>,
+    /// Smallest reward `distribute_rewards` pays any one staker.
+    #[serde(default)]
+    pub min_reward_per_user: Option<u128>,
+    #[serde(skip)]
+    on_distribute: DistributeHook,
+    #[serde(skip)]
+    strategy: CustomStrategy,
+    #[serde(skip)]
+    clock: SharedClock,
+}
+
+/// Callback run by `distribute_rewards` for every staker. Like the clock it is
+/// not part of the contract's state: clones and restored contracts start
+/// without one.
+#[derive(Default)]
+struct DistributeHook(Option<DistributeCallback>);
+
+/// A user-supplied `DistributionStrategy`, shared between clones. It never
+/// affects equality.
+#[derive(Clone, Default)]
+struct CustomStrategy(Option<Arc<dyn DistributionStrategy>>);
+
+impl fmt::Debug for CustomStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(if self.0.is_some() { "Some(..)" } else { "None" })
+    }
+}
+
+impl PartialEq for CustomStrategy {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+type DistributeCallback = Box<dyn FnMut(&str, u128) + Send>;
+
+impl Clone for DistributeHook {
+    fn clone(&self) -> Self {
+        DistributeHook(None)
+    }
+}
+
+impl fmt::Debug for DistributeHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(if self.0.is_some() { "Some(..)" } else { "None" })
+    }
+}
+
+impl PartialEq for DistributeHook {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+/// Version of the serialized contract state, stored in each contract.
+///
+/// * 0 (no `format_version` field): `total_coins` and reward amounts are
+///   `u64`.
+/// * 1: `total_coins` and reward amounts are `u128`. JSON written by version
+///   0 still loads, since every `u64` value fits; `to_bytes` output does
+///   not, so re-save older binary state from its JSON form.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The contract's clock, shared between clones. Clocks are not part of the
+/// contract's state, so they never affect equality.
+#[derive(Clone)]
+struct SharedClock(Arc<dyn Clock>);
+
+impl SharedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0.now()
+    }
+}
+
+/// Without `std` there is no system clock to fall back on, so a contract
+/// restored from serialized state must be given one with `set_clock`.
+#[cfg(not(feature = "std"))]
+struct UnsetClock;
+
+#[cfg(not(feature = "std"))]
+impl Clock for UnsetClock {
+    fn now(&self) -> DateTime<Utc> {
+        panic!("no clock configured; call Contract::set_clock");
+    }
+}
+
+impl Default for SharedClock {
+    #[cfg(feature = "std")]
+    fn default() -> Self {
+        SharedClock(Arc::new(SystemClock))
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn default() -> Self {
+        SharedClock(Arc::new(UnsetClock))
+    }
+}
+
+impl fmt::Debug for SharedClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Clock")
+    }
+}
+
+impl PartialEq for SharedClock {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+/// Serializes a `Duration` as whole milliseconds.
+mod duration_millis {
+    use chrono::Duration;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(duration.num_milliseconds())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let millis = i64::deserialize(deserializer)?;
+        Duration::try_milliseconds(millis).ok_or_else(|| D::Error::custom("duration out of range"))
+    }
+}
+
+/// The entries of a JSON roster object in document order. Unlike a map,
+/// this keeps repeated keys so they can be rejected.
+#[cfg(feature = "std")]
+struct RosterEntries(Vec<(String, u64)>);
+
+#[cfg(feature = "std")]
+impl<'de> Deserialize<'de> for RosterEntries {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EntriesVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for EntriesVisitor {
+            type Value = RosterEntries;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an object of users to stake amounts")
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<Self::Value, A::Error> {
+                let mut entries = vec![];
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(RosterEntries(entries))
+            }
+        }
+
+        deserializer.deserialize_map(EntriesVisitor)
+    }
+}
+
+impl Contract {
+    #[cfg(feature = "std")]
+    pub fn new(total_coins: u128) -> Self {
+        Contract::new_with_duration(total_coins, Duration::days(7))
+    }
+
+    #[cfg(feature = "std")]
+    pub fn new_with_duration(total_coins: u128, duration: Duration) -> Self {
+        Contract::new_with_clock(total_coins, duration, Box::new(SystemClock))
+    }
+
+    pub fn builder() -> ContractBuilder {
+        ContractBuilder::new()
+    }
+
+    #[cfg(feature = "std")]
+    pub fn new_with_min_stake(total_coins: u128, min_stake: u64) -> Self {
+        let mut contract = Contract::new(total_coins);
+        contract.min_stake = min_stake;
+        contract
+    }
+
+    #[cfg(feature = "std")]
+    pub fn new_with_owner(total_coins: u128, owner: String) -> Self {
+        let mut contract = Contract::new(total_coins);
+        contract.owner = owner;
+        contract
+    }
+
+    pub fn new_with_clock(total_coins: u128, duration: Duration, clock: Box<dyn Clock>) -> Self {
+        let now = clock.now();
+        Contract {
+            format_version: FORMAT_VERSION,
+            total_coins,
+            stakers: BTreeMap::new(),
+            start_date: now,
+            staking_duration: duration,
+            min_stake: 0,
+            max_total_stake: None,
+            early_withdraw_penalty_bps: 0,
+            min_stakers_for_distribution: 0,
+            reward_tiers: vec![],
+            normalize_usernames: false,
+            whitelist: None,
+            referral_bonus_bps: 0,
+            referred_by: BTreeMap::new(),
+            delegations: BTreeMap::new(),
+            frozen: BTreeSet::new(),
+            stake_fee_bps: 0,
+            fee_pool: 0,
+            slash_to_pool: false,
+            pool_policy: PoolPolicy::Fixed,
+            sweep_to_pool: false,
+            unclaimed: BTreeMap::new(),
+            accrued_epoch: None,
+            epoch: 0,
+            epoch_history: vec![],
+            lifetime_distributed: 0,
+            counted_epoch: None,
+            paused: false,
+            finalized: false,
+            final_rewards: vec![],
+            distributed_at: None,
+            vesting_duration: Duration::zero(),
+            vesting_cliff: Duration::zero(),
+            owner: String::new(),
+            events: vec![],
+            stake_cooldown: Duration::zero(),
+            last_stake_at: BTreeMap::new(),
+            stake_history: BTreeMap::new(),
+            grace_period: Duration::zero(),
+            distribution_mode: DistributionMode::Proportional,
+            auto_finalize: false,
+            rounding_mode: RoundingMode::Floor,
+            tie_break: TieBreak::ByName,
+            precision_scale: 0,
+            reward_pools: BTreeMap::new(),
+            max_reward_per_user: None,
+            min_reward_per_user: None,
+            on_distribute: DistributeHook::default(),
+            strategy: CustomStrategy::default(),
+            clock: SharedClock(Arc::from(clock)),
+        }
+    }
+
+    pub fn owner(&self) -> &str {
+        &self.owner
+    }
+
+    /// Fails with `Unauthorized` unless `caller` is the owner. A contract
+    /// without an owner refuses every caller.
+    pub fn require_owner(&self, caller: &str) -> Result<(), StakingError> {
+        if !self.owner.is_empty() && self.owner == caller {
+            Ok(())
+        } else {
+            Err(StakingError::Unauthorized)
+        }
+    }
+
+    /// Hands the owner's privileges to `new_owner`, which must not be empty.
+    pub fn transfer_ownership(
+        &mut self,
+        caller: &str,
+        new_owner: String,
+    ) -> Result<(), StakingError> {
+        self.require_owner(caller)?;
+        if new_owner.trim().is_empty() {
+            return Err(StakingError::InvalidUser);
+        }
+        self.owner = new_owner;
+        Ok(())
+    }
+
+    /// Registers `callback` to be called by `distribute_rewards` with each
+    /// staker's username and reward, replacing any earlier callback.
+    pub fn on_distribute(&mut self, callback: impl FnMut(&str, u128) + Send + 'static) {
+        self.on_distribute = DistributeHook(Some(Box::new(callback)));
+    }
+
+    /// Replaces the contract's clock, e.g. after restoring saved state.
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = SharedClock(Arc::from(clock));
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            total_coins: self.total_coins,
+            stakers: self.stakers.clone(),
+            start_date: self.start_date,
+            total_staked: self.total_staked(),
+        }
+    }
+
+    /// SHA-256 over `total_coins`, `start_date` and each staker's name and
+    /// amount in name order, so equal pools always hash alike.
+    pub fn state_hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.total_coins.to_le_bytes());
+        hasher.update(self.start_date.timestamp().to_le_bytes());
+        hasher.update(self.start_date.timestamp_subsec_nanos().to_le_bytes());
+        hasher.update((self.stakers.len() as u64).to_le_bytes());
+        for (user, entry) in &self.stakers {
+            hasher.update((user.len() as u64).to_le_bytes());
+            hasher.update(user.as_bytes());
+            hasher.update(entry.amount.to_le_bytes());
+        }
+        hasher.finalize().into()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("contract state is always serializable")
+    }
+
+    /// Restores a contract saved with `to_json`; the restored contract reads
+    /// the system clock.
+    pub fn from_json(s: &str) -> Result<Self, serde_json::Error> {
+        let mut contract: Contract = serde_json::from_str(s)?;
+        contract.format_version = FORMAT_VERSION;
+        Ok(contract)
+    }
+
+    /// Compact binary form of the contract, for storage where JSON is too
+    /// large.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serde::encode_to_vec(self, bincode::config::standard())
+            .expect("contract state is always serializable")
+    }
+
+    /// Restores a contract saved with `to_bytes`; like `from_json`, the
+    /// restored contract reads the system clock.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, StakingError> {
+        let (contract, _) = bincode::serde::decode_from_slice(bytes, bincode::config::standard())
+            .map_err(|err| StakingError::InvalidBytes(err.to_string()))?;
+        Ok(contract)
+    }
+
+    /// Opens a contract with a pool of `pool` whose stakers come from a JSON
+    /// object of `{ "user": amount }` pairs, all staked now. Entries are
+    /// checked like ordinary stakes, and a user listed twice, including
+    /// under names that only differ by surrounding whitespace, is rejected.
+    #[cfg(feature = "std")]
+    pub fn from_roster_json(pool: u128, roster_json: &str) -> Result<Contract, StakingError> {
+        let RosterEntries(roster) = serde_json::from_str(roster_json)
+            .map_err(|err| StakingError::InvalidRoster(err.to_string()))?;
+        let mut contract = Contract::new(pool);
+        let mut seen = BTreeSet::new();
+        for (user, _) in &roster {
+            if !seen.insert(contract.normalize_user(user)) {
+                return Err(StakingError::InvalidRoster(format!(
+                    "duplicate user `{}`",
+                    user
+                )));
+            }
+        }
+        for (user, amount) in roster {
+            contract.try_stake(user, amount)?;
+        }
+        Ok(contract)
+    }
+
+    pub fn stake(&mut self, user: String, amount: u64) {
+        if let Err(err) = self.try_stake(user, amount) {
+            panic!("{}", err);
+        }
+    }
+
+    pub fn try_stake(&mut self, user: String, amount: u64) -> Result<(), StakingError> {
+        let user = self.normalize_user(&user);
+        self.validate_stake(&user, amount, 0)?;
+        let now = self.clock.now();
+        let net = self.take_fee(amount);
+        self.record_stake(&user, net, now);
+        self.credit_stake(user, StakeEntry::deposit(net, now));
+        Ok(())
+    }
+
+    /// Stakes `amount` locked for `lock`. The whole position cannot be
+    /// unstaked until the lock expires, and earns a bonus under
+    /// `distribute_lockup_rewards`.
+    pub fn stake_locked(
+        &mut self,
+        user: String,
+        amount: u64,
+        lock: Duration,
+    ) -> Result<(), StakingError> {
+        let user = self.normalize_user(&user);
+        self.validate_stake(&user, amount, 0)?;
+        let now = self.clock.now();
+        let net = self.take_fee(amount);
+        let mut deposit = StakeEntry::deposit(net, now);
+        if lock > Duration::zero() {
+            deposit.lock = lock;
+            deposit.locked_until = Some(add_saturating(now, lock));
+        }
+        self.record_stake(&user, net, now);
+        self.credit_stake(user, deposit);
+        Ok(())
+    }
+
+    /// Stakes like `try_stake` and records `referrer` as the one who brought
+    /// `user` in. Only a user's first referrer counts, and self-referrals are
+    /// ignored.
+    pub fn stake_with_referral(
+        &mut self,
+        user: String,
+        amount: u64,
+        referrer: Option<String>,
+    ) -> Result<(), StakingError> {
+        let user = self.normalize_user(&user);
+        self.try_stake(user.clone(), amount)?;
+        if let Some(referrer) = referrer.map(|referrer| self.normalize_user(&referrer)) {
+            if referrer != user {
+                self.referred_by.entry(user).or_insert(referrer);
+            }
+        }
+        Ok(())
+    }
+
+    /// Stakes `amount` funded by `delegator` on behalf of `beneficiary`, who
+    /// becomes the staker and earns the rewards. Only a beneficiary's first
+    /// delegator is recorded.
+    pub fn delegate_stake(
+        &mut self,
+        delegator: String,
+        beneficiary: String,
+        amount: u64,
+    ) -> Result<(), StakingError> {
+        let delegator = self.normalize_user(&delegator);
+        if delegator.is_empty() {
+            return Err(StakingError::InvalidUser);
+        }
+        let beneficiary = self.normalize_user(&beneficiary);
+        self.try_stake(beneficiary.clone(), amount)?;
+        self.delegations.entry(beneficiary).or_insert(delegator);
+        Ok(())
+    }
+
+    /// Who funded `beneficiary`'s stake through `delegate_stake`, if anyone.
+    pub fn delegator_of(&self, beneficiary: &str) -> Option<&str> {
+        self.delegations
+            .get(&self.normalize_user(beneficiary))
+            .map(String::as_str)
+    }
+
+    /// Number of users `referrer` brought in who are still staking.
+    pub fn referral_count(&self, referrer: &str) -> u64 {
+        let referrer = &self.normalize_user(referrer);
+        self.referred_by
+            .iter()
+            .filter(|(user, by)| *by == referrer && self.stakers.contains_key(*user))
+            .count() as u64
+    }
+
+    /// Stakes every entry, or none of them if any entry would be rejected.
+    /// A user listed more than once is subject to `stake_cooldown` between
+    /// their entries, as if they were staked one after another.
+    pub fn batch_stake(&mut self, entries: Vec<(String, u64)>) -> Result<(), StakingError> {
+        let entries: Vec<(String, u64)> = entries
+            .into_iter()
+            .map(|(user, amount)| (self.normalize_user(&user), amount))
+            .collect();
+        let mut pending = 0u64;
+        let mut seen = BTreeSet::new();
+        for (user, amount) in &entries {
+            self.validate_stake(user, *amount, pending)?;
+            if !seen.insert(user) && self.stake_cooldown > Duration::zero() {
+                return Err(StakingError::CooldownActive);
+            }
+            pending = pending.saturating_add(*amount);
+        }
+        let now = self.clock.now();
+        for (user, amount) in entries {
+            let net = self.take_fee(amount);
+            self.record_stake(&user, net, now);
+            self.credit_stake(user, StakeEntry::deposit(net, now));
+        }
+        Ok(())
+    }
+
+    /// Sets `user`'s stake to exactly `amount`, for operator corrections; zero
+    /// removes the staker. An existing position keeps its deposit time and
+    /// lock. Limits such as `min_stake` and the pool cap are not applied. The
+    /// change is logged as a stake or unstake and reflected in
+    /// `stake_history`, newest deposits being reduced first.
+    pub fn set_stake(
+        &mut self,
+        caller: &str,
+        user: String,
+        amount: u64,
+    ) -> Result<(), StakingError> {
+        self.require_owner(caller)?;
+        let user = self.normalize_user(&user);
+        if user.trim().is_empty() {
+            return Err(StakingError::InvalidUser);
+        }
+        match self.state() {
+            ContractState::Open => {}
+            ContractState::Paused => return Err(StakingError::Paused),
+            ContractState::Closed => return Err(StakingError::WindowClosed),
+            ContractState::Finalized => return Err(StakingError::Finalized),
+        }
+        let now = self.clock.now();
+        let current = self.get_stake(&user).unwrap_or(0);
+        if amount > current {
+            let added = amount - current;
+            self.stake_history
+                .entry(user.clone())
+                .or_default()
+                .push((added, now));
+            self.events.push(Event::Staked {
+                user: user.clone(),
+                amount: added,
+                at: now,
+            });
+        } else if amount < current {
+            let removed = current - amount;
+            self.trim_history(&user, removed);
+            self.events.push(Event::Unstaked {
+                user: user.clone(),
+                amount: removed,
+                at: now,
+            });
+        }
+        if amount == 0 {
+            self.remove_position(&user);
+            return Ok(());
+        }
+        self.stakers
+            .entry(user)
+            .or_insert_with(|| StakeEntry::deposit(0, now))
+            .amount = amount;
+        Ok(())
+    }
+
+    /// Approves `user` to stake. The first approval turns an open pool into a
+    /// permissioned one.
+    pub fn allow(&mut self, user: String) {
+        let user = self.normalize_user(&user);
+        self.whitelist
+            .get_or_insert_with(BTreeSet::new)
+            .insert(user);
+    }
+
+    /// Revokes `user`'s approval; existing stakes are left in place.
+    pub fn disallow(&mut self, user: &str) {
+        let user = self.normalize_user(user);
+        if let Some(whitelist) = self.whitelist.as_mut() {
+            whitelist.remove(&user);
+        }
+    }
+
+    /// Stops `user` from unstaking, withdrawing, claiming or transferring.
+    /// Their stake keeps earning rewards.
+    pub fn freeze(&mut self, caller: &str, user: String) -> Result<(), StakingError> {
+        self.require_owner(caller)?;
+        let user = self.normalize_user(&user);
+        self.frozen.insert(user);
+        Ok(())
+    }
+
+    /// Lifts a freeze placed with `freeze`.
+    pub fn unfreeze(&mut self, caller: &str, user: &str) -> Result<(), StakingError> {
+        self.require_owner(caller)?;
+        let user = self.normalize_user(user);
+        self.frozen.remove(&user);
+        Ok(())
+    }
+
+    pub fn is_frozen(&self, user: &str) -> bool {
+        self.frozen.contains(&self.normalize_user(user))
+    }
+
+    fn require_unfrozen(&self, user: &str) -> Result<(), StakingError> {
+        if self.is_frozen(user) {
+            return Err(StakingError::Frozen);
+        }
+        Ok(())
+    }
+
+    /// Drains the accumulated staking fees.
+    pub fn withdraw_fees(&mut self, caller: &str) -> Result<u64, StakingError> {
+        self.require_owner(caller)?;
+        Ok(core::mem::take(&mut self.fee_pool))
+    }
+
+    /// Each deposit `user` has staked, as `(amount, staked_at)` oldest first.
+    /// Withdrawals, transfers and slashes take from the newest deposits, so
+    /// the amounts always add up to their stake.
+    pub fn stake_history(&self, user: &str) -> Option<&[(u64, DateTime<Utc>)]> {
+        self.stake_history
+            .get(&self.normalize_user(user))
+            .map(Vec::as_slice)
+    }
+
+    /// The ordered log of stakes, unstakes and distributions.
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    fn record_stake(&mut self, user: &str, amount: u64, at: DateTime<Utc>) {
+        self.last_stake_at.insert(user.to_string(), at);
+        if amount > 0 {
+            self.stake_history
+                .entry(user.to_string())
+                .or_default()
+                .push((amount, at));
+            self.events.push(Event::Staked {
+                user: user.to_string(),
+                amount,
+                at,
+            });
+        }
+    }
+
+    /// Takes `amount` off `user`'s most recent deposits in `stake_history`
+    /// and returns what was taken, oldest first.
+    fn trim_history(&mut self, user: &str, mut amount: u64) -> Vec<(u64, DateTime<Utc>)> {
+        let mut taken_deposits = vec![];
+        let Some(deposits) = self.stake_history.get_mut(user) else {
+            return taken_deposits;
+        };
+        while amount > 0 {
+            let Some(last) = deposits.last_mut() else {
+                break;
+            };
+            let taken = amount.min(last.0);
+            last.0 -= taken;
+            amount -= taken;
+            taken_deposits.push((taken, last.1));
+            if last.0 == 0 {
+                deposits.pop();
+            }
+        }
+        if deposits.is_empty() {
+            self.stake_history.remove(user);
+        }
+        taken_deposits.reverse();
+        taken_deposits
+    }
+
+    /// Removes `user`'s whole position along with its `stake_history`.
+    fn remove_position(&mut self, user: &str) -> Option<StakeEntry> {
+        self.stake_history.remove(user);
+        self.stakers.remove(user)
+    }
+
+    /// Moves `stake_fee_bps` of a deposit into `fee_pool` and returns the rest.
+    fn take_fee(&mut self, amount: u64) -> u64 {
+        let fee_bps = self.stake_fee_bps.min(10_000);
+        let fee = (amount as u128 * fee_bps as u128 / 10_000) as u64;
+        self.fee_pool = self.fee_pool.saturating_add(fee);
+        amount - fee
+    }
+
+    /// Trims surrounding whitespace, so " Alice " and "Alice" are the same
+    /// staker, and lowercases if `normalize_usernames` is set.
+    fn normalize_user(&self, user: &str) -> String {
+        let trimmed = user.trim();
+        if self.normalize_usernames {
+            trimmed.to_lowercase()
+        } else {
+            trimmed.to_string()
+        }
+    }
+
+    /// Checks that `user` is a non-empty name allowed by the whitelist.
+    fn validate_user(&self, user: &str) -> Result<(), StakingError> {
+        if user.trim().is_empty() {
+            return Err(StakingError::InvalidUser);
+        }
+        if self
+            .whitelist
+            .as_ref()
+            .is_some_and(|whitelist| !whitelist.contains(user))
+        {
+            return Err(StakingError::NotWhitelisted);
+        }
+        Ok(())
+    }
+
+    /// Checks whether `user` may stake `amount` on top of `pending` tokens not
+    /// yet credited to the pool.
+    fn validate_stake(&self, user: &str, amount: u64, pending: u64) -> Result<(), StakingError> {
+        self.validate_user(user)?;
+        match self.state() {
+            ContractState::Open => {}
+            ContractState::Closed if self.in_grace_period() && self.stakers.contains_key(user) => {}
+            ContractState::Paused => return Err(StakingError::Paused),
+            ContractState::Closed => return Err(StakingError::WindowClosed),
+            ContractState::Finalized => return Err(StakingError::Finalized),
+        }
+        if amount == 0 {
+            return Err(StakingError::ZeroAmount);
+        }
+        if amount < self.min_stake {
+            return Err(StakingError::BelowMinimum);
+        }
+        if self
+            .last_stake_at
+            .get(user)
+            .is_some_and(|last| self.clock.now() < add_saturating(*last, self.stake_cooldown))
+        {
+            return Err(StakingError::CooldownActive);
+        }
+        if let Some(cap) = self.max_total_stake {
+            let total = self.total_staked().saturating_add(pending);
+            if total.saturating_add(amount) > cap {
+                return Err(StakingError::CapExceeded);
+            }
+        }
+        Ok(())
+    }
+
+    /// Withdraws `amount` from `user`'s position and returns what they receive.
+    /// While the window is still open, `early_withdraw_penalty_bps` of the
+    /// amount is kept back and added to `total_coins`, after `pool_policy` has
+    /// been applied.
+    pub fn unstake(&mut self, user: &str, amount: u64) -> Result<u64, StakingError> {
+        if amount == 0 {
+            return Err(StakingError::ZeroAmount);
+        }
+        let user = &self.normalize_user(user);
+        let entry = self.stakers.get(user).ok_or(StakingError::UnknownStaker)?;
+        self.require_unfrozen(user)?;
+        if entry
+            .locked_until
+            .is_some_and(|locked_until| self.clock.now() < locked_until)
+        {
+            return Err(StakingError::StillLocked);
+        }
+        let penalty = if self.is_staking_open() {
+            let penalty_bps = self.early_withdraw_penalty_bps.min(10_000);
+            (amount as u128 * penalty_bps as u128 / 10_000) as u64
+        } else {
+            0
+        };
+        let total_staked = self.total_staked();
+        self.debit_stake(user, amount)?;
+        self.trim_history(user, amount);
+        if self.pool_policy == PoolPolicy::Burn {
+            self.total_coins -= mul_div(self.total_coins, amount as u128, total_staked as u128);
+        }
+        self.events.push(Event::Unstaked {
+            user: user.to_string(),
+            amount,
+            at: self.clock.now(),
+        });
+        self.total_coins = self.total_coins.saturating_add(penalty as u128);
+        Ok(amount - penalty)
+    }
+
+    /// Returns `user`'s whole stake and removes them, ignoring locks and
+    /// early-withdrawal penalties. Only available while the contract is
+    /// paused, and not to frozen stakers.
+    pub fn emergency_withdraw(&mut self, user: &str) -> Result<u64, StakingError> {
+        let user = &self.normalize_user(user);
+        if !self.paused {
+            return Err(StakingError::NotPaused);
+        }
+        self.require_unfrozen(user)?;
+        let entry = self
+            .remove_position(user)
+            .ok_or(StakingError::UnknownStaker)?;
+        self.events.push(Event::Unstaked {
+            user: user.to_string(),
+            amount: entry.amount,
+            at: self.clock.now(),
+        });
+        Ok(entry.amount)
+    }
+
+    /// Ejects `user` entirely, ignoring locks and penalties, and returns the
+    /// amount they had staked.
+    pub fn remove_staker(&mut self, caller: &str, user: &str) -> Result<u64, StakingError> {
+        self.require_owner(caller)?;
+        let user = &self.normalize_user(user);
+        let entry = self
+            .remove_position(user)
+            .ok_or(StakingError::UnknownStaker)?;
+        self.events.push(Event::Unstaked {
+            user: user.to_string(),
+            amount: entry.amount,
+            at: self.clock.now(),
+        });
+        Ok(entry.amount)
+    }
+
+    /// Folds `other` into this contract: its pool is added to `total_coins`
+    /// and its stakers are credited here, summing positions held in both.
+    /// Their unclaimed rewards, fees, deposit history, cooldowns, freezes,
+    /// delegators and referrers come along too; where both contracts name
+    /// a delegator or referrer for the same user, this contract's is kept.
+    /// Incoming stakers must pass this contract's whitelist and `min_stake`,
+    /// and the combined stake must fit `max_total_stake`; otherwise neither
+    /// contract is changed.
+    pub fn merge(&mut self, other: Contract) -> Result<(), StakingError> {
+        if self.finalized || other.finalized {
+            return Err(StakingError::Finalized);
+        }
+        let incoming: Vec<(String, StakeEntry)> = other
+            .stakers
+            .into_iter()
+            .map(|(user, entry)| (self.normalize_user(&user), entry))
+            .collect();
+        for (user, entry) in &incoming {
+            self.validate_user(user)?;
+            let combined = self
+                .get_stake(user)
+                .unwrap_or(0)
+                .saturating_add(entry.amount);
+            if combined < self.min_stake {
+                return Err(StakingError::BelowMinimum);
+            }
+        }
+        if let Some(cap) = self.max_total_stake {
+            let total = incoming
+                .iter()
+                .fold(self.total_staked(), |total, (_, entry)| {
+                    total.saturating_add(entry.amount)
+                });
+            if total > cap {
+                return Err(StakingError::CapExceeded);
+            }
+        }
+        self.total_coins = self.total_coins.saturating_add(other.total_coins);
+        self.fee_pool = self.fee_pool.saturating_add(other.fee_pool);
+        let now = self.clock.now();
+        for (user, entry) in incoming {
+            if entry.amount > 0 {
+                self.events.push(Event::Staked {
+                    user: user.clone(),
+                    amount: entry.amount,
+                    at: now,
+                });
+            }
+            self.credit_stake(user, entry);
+        }
+        for (user, amount) in other.unclaimed {
+            let unclaimed = self
+                .unclaimed
+                .entry(self.normalize_user(&user))
+                .or_insert(0);
+            *unclaimed = unclaimed.saturating_add(amount);
+        }
+        for (user, deposits) in other.stake_history {
+            let history = self
+                .stake_history
+                .entry(self.normalize_user(&user))
+                .or_default();
+            history.extend(deposits);
+            history.sort_by_key(|(_, at)| *at);
+        }
+        for (user, at) in other.last_stake_at {
+            let last = self
+                .last_stake_at
+                .entry(self.normalize_user(&user))
+                .or_insert(at);
+            *last = (*last).max(at);
+        }
+        for user in other.frozen {
+            self.frozen.insert(self.normalize_user(&user));
+        }
+        for (user, delegator) in other.delegations {
+            let delegator = self.normalize_user(&delegator);
+            self.delegations
+                .entry(self.normalize_user(&user))
+                .or_insert(delegator);
+        }
+        for (user, referrer) in other.referred_by {
+            let referrer = self.normalize_user(&referrer);
+            self.referred_by
+                .entry(self.normalize_user(&user))
+                .or_insert(referrer);
+        }
+        Ok(())
+    }
+
+    /// Moves `amount` of `from`'s position to `to`, keeping its deposit time.
+    /// Unlike staking, this is allowed after the window has closed, but `to`
+    /// must still be a valid, whitelisted user.
+    pub fn transfer_stake(
+        &mut self,
+        from: &str,
+        to: String,
+        amount: u64,
+    ) -> Result<(), StakingError> {
+        if amount == 0 {
+            return Err(StakingError::ZeroAmount);
+        }
+        let from = &self.normalize_user(from);
+        let to = self.normalize_user(&to);
+        self.validate_user(&to)?;
+        self.require_unfrozen(from)?;
+        let portion = self.debit_stake(from, amount)?;
+        let moved = self.trim_history(from, amount);
+        let history = self.stake_history.entry(to.clone()).or_default();
+        history.extend(moved);
+        history.sort_by_key(|(_, at)| *at);
+        let now = self.clock.now();
+        self.events.push(Event::Unstaked {
+            user: from.to_string(),
+            amount,
+            at: now,
+        });
+        self.events.push(Event::Staked {
+            user: to.clone(),
+            amount,
+            at: now,
+        });
+        self.credit_stake(to, portion);
+        Ok(())
+    }
+
+    /// Cuts up to `amount` from `user`'s position and returns how much was
+    /// actually slashed.
+    pub fn slash(&mut self, caller: &str, user: &str, amount: u64) -> Result<u64, StakingError> {
+        self.require_owner(caller)?;
+        let user = &self.normalize_user(user);
+        let staked = self
+            .stakers
+            .get(user)
+            .ok_or(StakingError::UnknownStaker)?
+            .amount;
+        let slashed = amount.min(staked);
+        self.debit_stake(user, slashed)?;
+        self.trim_history(user, slashed);
+        self.events.push(Event::Unstaked {
+            user: user.to_string(),
+            amount: slashed,
+            at: self.clock.now(),
+        });
+        if self.slash_to_pool {
+            self.total_coins = self.total_coins.saturating_add(slashed as u128);
+        }
+        Ok(slashed)
+    }
+
+    /// Removes every staker holding less than `threshold` and returns what
+    /// they held, in name order. Only the owner may sweep.
+    pub fn sweep_dust(
+        &mut self,
+        caller: &str,
+        threshold: u64,
+    ) -> Result<Vec<(String, u64)>, StakingError> {
+        self.require_owner(caller)?;
+        let swept: Vec<(String, u64)> = self
+            .stakers
+            .iter()
+            .filter(|(_, entry)| entry.amount < threshold)
+            .map(|(user, entry)| (user.clone(), entry.amount))
+            .collect();
+        let now = self.clock.now();
+        for (user, amount) in &swept {
+            self.remove_position(user);
+            self.events.push(Event::Unstaked {
+                user: user.clone(),
+                amount: *amount,
+                at: now,
+            });
+            if self.sweep_to_pool {
+                self.total_coins = self.total_coins.saturating_add(*amount as u128);
+            }
+        }
+        Ok(swept)
+    }
+
+    fn credit_stake(&mut self, user: String, deposit: StakeEntry) {
+        if deposit.amount == 0 {
+            return;
+        }
+        let entry = self.stakers.entry(user).or_insert(StakeEntry {
+            amount: 0,
+            ..deposit
+        });
+        // A top-up moves `staked_at` to the amount-weighted average deposit
+        // time, so `amount * seconds_staked` still matches the deposits.
+        let elapsed = (deposit.staked_at - entry.staked_at).num_milliseconds() as i128;
+        let shift = elapsed * deposit.amount as i128
+            / (entry.amount as i128 + deposit.amount as i128).max(1);
+        entry.staked_at += Duration::milliseconds(shift as i64);
+        entry.amount = entry.amount.saturating_add(deposit.amount);
+        entry.lock = entry.lock.max(deposit.lock);
+        entry.locked_until = entry.locked_until.max(deposit.locked_until);
+    }
+
+    /// Removes `amount` from `user`'s position and returns the removed portion.
+    fn debit_stake(&mut self, user: &str, amount: u64) -> Result<StakeEntry, StakingError> {
+        let entry = self
+            .stakers
+            .get_mut(user)
+            .ok_or(StakingError::UnknownStaker)?;
+        if amount > entry.amount {
+            return Err(StakingError::InsufficientStake);
+        }
+        let portion = StakeEntry {
+            amount,
+            ..entry.clone()
+        };
+        entry.amount -= amount;
+        if entry.amount == 0 {
+            self.stakers.remove(user);
+        }
+        Ok(portion)
+    }
+
+    /// The current lifecycle state. Finalization takes precedence over a
+    /// pause, and a pause over the window having closed.
+    pub fn state(&self) -> ContractState {
+        if self.finalized {
+            ContractState::Finalized
+        } else if self.paused {
+            ContractState::Paused
+        } else if !self.is_staking_open() {
+            ContractState::Closed
+        } else {
+            ContractState::Open
+        }
+    }
+
+    /// When the staking window closes, including any `extend_window`
+    /// extensions. A window too long to represent never closes.
+    pub fn deadline(&self) -> DateTime<Utc> {
+        add_saturating(self.start_date, self.staking_duration)
+    }
+
+    pub fn is_staking_open(&self) -> bool {
+        self.clock.now() < self.deadline()
+    }
+
+    /// Pushes the staking deadline out by `additional`, reopening the window if
+    /// it has already closed. Only the owner may extend the window, and only
+    /// by a positive duration.
+    pub fn extend_window(
+        &mut self,
+        caller: &str,
+        additional: Duration,
+    ) -> Result<(), StakingError> {
+        self.require_owner(caller)?;
+        if self.finalized {
+            return Err(StakingError::Finalized);
+        }
+        if additional <= Duration::zero() {
+            return Err(StakingError::InvalidDuration);
+        }
+        self.staking_duration = self
+            .staking_duration
+            .checked_add(&additional)
+            .unwrap_or(Duration::milliseconds(i64::MAX));
+        Ok(())
+    }
+
+    /// Whether the window has closed but `grace_period` is still running.
+    fn in_grace_period(&self) -> bool {
+        let now = self.clock.now();
+        let close = self.deadline();
+        now >= close && now < add_saturating(close, self.grace_period)
+    }
+
+    /// Time left before the staking window closes, or zero once it has.
+    pub fn remaining_window(&self) -> Duration {
+        let remaining = self.deadline() - self.clock.now();
+        remaining.max(Duration::zero())
+    }
+
+    pub fn get_stake(&self, user: &str) -> Option<u64> {
+        self.stakers
+            .get(&self.normalize_user(user))
+            .map(|entry| entry.amount)
+    }
+
+    pub fn total_staked(&self) -> u64 {
+        self.stakers
+            .values()
+            .fold(0u64, |total, entry| total.saturating_add(entry.amount))
+    }
+
+    pub fn staker_count(&self) -> usize {
+        self.stakers.len()
+    }
+
+    /// Current stakers who made a deposit within `[from, to]`, with how much
+    /// of their stake those deposits still make up, ordered by their first
+    /// deposit in the range. Withdrawn deposits do not count.
+    pub fn stakers_in_range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<(String, u64)> {
+        let mut in_range: Vec<(DateTime<Utc>, String, u64)> = self
+            .stake_history
+            .iter()
+            .filter(|(user, _)| self.stakers.contains_key(*user))
+            .filter_map(|(user, deposits)| {
+                let mut deposits = deposits
+                    .iter()
+                    .filter(|(_, at)| *at >= from && *at <= to)
+                    .peekable();
+                let first = deposits.peek()?.1;
+                let total = deposits.fold(0u64, |total, (amount, _)| total.saturating_add(*amount));
+                Some((first, user.clone(), total))
+            })
+            .collect();
+        in_range.sort_by_key(|(first, _, _)| *first);
+        in_range
+            .into_iter()
+            .map(|(_, user, total)| (user, total))
+            .collect()
+    }
+
+    /// Median stake amount, averaging (rounded down) the two middle stakes
+    /// when there is an even number of stakers.
+    pub fn median_stake(&self) -> Option<u64> {
+        let mut amounts: Vec<u64> = self.stakers.values().map(|entry| entry.amount).collect();
+        if amounts.is_empty() {
+            return None;
+        }
+        amounts.sort_unstable();
+        let middle = amounts.len() / 2;
+        if amounts.len() % 2 == 1 {
+            Some(amounts[middle])
+        } else {
+            Some(((amounts[middle - 1] as u128 + amounts[middle] as u128) / 2) as u64)
+        }
+    }
+
+    /// Mean stake amount, rounded down.
+    pub fn mean_stake(&self) -> Option<u64> {
+        if self.stakers.is_empty() {
+            return None;
+        }
+        let total = self
+            .stakers
+            .values()
+            .map(|entry| entry.amount as u128)
+            .sum::<u128>();
+        Some((total / self.stakers.len() as u128) as u64)
+    }
+
+    /// Stakers ordered by stake size, largest first; ties are ordered by
+    /// `tie_break`.
+    pub fn ranked_stakers(&self) -> Vec<(String, u64)> {
+        let mut ranked: Vec<(String, u64)> = self
+            .stakers
+            .iter()
+            .map(|(user, entry)| (user.clone(), entry.amount))
+            .collect();
+        ranked.sort_by(|(a_user, a_amount), (b_user, b_amount)| {
+            b_amount
+                .cmp(a_amount)
+                .then_with(|| self.tie_break.order(a_user, b_user))
+        });
+        ranked
+    }
+
+    /// `user`'s 1-based rank by stake size together with the number of
+    /// stakers. Equal stakes share the best rank among them, so two stakers
+    /// tied for first are both `(1, n)`.
+    pub fn rank_of(&self, user: &str) -> Option<(usize, usize)> {
+        let amount = self.get_stake(user)?;
+        let above = self
+            .stakers
+            .values()
+            .filter(|entry| entry.amount > amount)
+            .count();
+        Some((above + 1, self.staker_count()))
+    }
+
+    /// Emergency stop: blocks staking until `unpause`, without finalizing.
+    pub fn pause(&mut self, caller: &str) -> Result<(), StakingError> {
+        self.require_owner(caller)?;
+        self.paused = true;
+        Ok(())
+    }
+
+    pub fn unpause(&mut self, caller: &str) -> Result<(), StakingError> {
+        self.require_owner(caller)?;
+        self.paused = false;
+        Ok(())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Records the current epoch's distribution and starts the next epoch
+    /// with a pool of `new_pool`. Stakes carry over unchanged. A finalized
+    /// contract has no further epochs. Only the owner may advance the epoch.
+    pub fn advance_epoch(&mut self, caller: &str, new_pool: u128) -> Result<(), StakingError> {
+        self.require_owner(caller)?;
+        if self.finalized {
+            return Err(StakingError::Finalized);
+        }
+        let rewards = self.current_rewards();
+        let total = rewards.iter().map(|(_, reward)| reward).sum::<u128>();
+        self.count_distributed(total);
+        self.epoch_history.push(rewards);
+        self.epoch += 1;
+        self.total_coins = new_pool;
+        Ok(())
+    }
+
+    /// Total paid out over the contract's life: each epoch's pool once,
+    /// whether it was paid by `distribute_rewards` or closed by
+    /// `advance_epoch`, plus every `distribute_fraction` release. Repeat
+    /// distributions within an epoch, the locked-in rewards of a finalized
+    /// contract and previews such as `distribute` are not counted again.
+    pub fn lifetime_distributed(&self) -> u128 {
+        self.lifetime_distributed
+    }
+
+    /// Adds `total` to `lifetime_distributed` unless this epoch's
+    /// distribution has already been counted.
+    fn count_distributed(&mut self, total: u128) {
+        if self.counted_epoch != Some(self.epoch) {
+            self.counted_epoch = Some(self.epoch);
+            self.lifetime_distributed = self.lifetime_distributed.saturating_add(total);
+        }
+    }
+
+    /// The distribution recorded when `epoch` ended, if it has.
+    pub fn distribution_for_epoch(&self, epoch: u64) -> Option<&[(String, u128)]> {
+        self.epoch_history
+            .get(usize::try_from(epoch).ok()?)
+            .map(Vec::as_slice)
+    }
+
+    /// Locks in the current distribution. Afterwards staking is rejected and
+    /// `distribute_rewards` keeps returning the locked-in result.
+    pub fn finalize(&mut self, caller: &str) -> Result<(), StakingError> {
+        self.require_owner(caller)?;
+        self.lock_in_rewards();
+        Ok(())
+    }
+
+    fn lock_in_rewards(&mut self) {
+        if !self.finalized {
+            self.final_rewards = self.current_rewards();
+            self.finalized = true;
+            self.distributed_at = Some(self.clock.now());
+        }
+    }
+
+    pub fn is_finalized(&self) -> bool {
+        self.finalized
+    }
+
+    /// How much of `user`'s finalized reward has vested by `now`. Rewards
+    /// vest linearly over `vesting_duration` from `finalize`, but nothing is
+    /// released until `vesting_cliff` has passed, at which point everything
+    /// vested so far unlocks at once.
+    pub fn vested_amount(&self, user: &str, now: DateTime<Utc>) -> u128 {
+        let user = &self.normalize_user(user);
+        let Some(distributed_at) = self.distributed_at else {
+            return 0;
+        };
+        let Some((_, reward)) = self.final_rewards.iter().find(|(staker, _)| staker == user) else {
+            return 0;
+        };
+        if now - distributed_at < self.vesting_cliff {
+            return 0;
+        }
+        let elapsed = (now - distributed_at).num_milliseconds().max(0) as u128;
+        let duration = self.vesting_duration.num_milliseconds().max(0) as u128;
+        if elapsed >= duration {
+            return *reward;
+        }
+        mul_div(*reward, elapsed, duration)
+    }
+
+    /// `distribute_rewards`, refused while fewer than
+    /// `min_stakers_for_distribution` stakers have joined, when there are no
+    /// coins to distribute, or when the pool cannot pay every staker
+    /// `min_reward_per_user`.
+    pub fn try_distribute_rewards(&mut self) -> Result<Vec<(String, u128)>, StakingError> {
+        if self.staker_count() < self.min_stakers_for_distribution {
+            return Err(StakingError::TooFewStakers);
+        }
+        if self.total_coins == 0 {
+            return Err(StakingError::EmptyPool);
+        }
+        if !self.finalized && !self.floor_covered(self.staker_count()) {
+            return Err(StakingError::InsufficientPool);
+        }
+        Ok(self.distribute_rewards())
+    }
+
+    /// `distribute_rewards` keyed by username.
+    pub fn distribute_rewards_map(&mut self) -> BTreeMap<String, u128> {
+        self.distribute_rewards().into_iter().collect()
+    }
+
+    /// Splits `total_coins` among stakers by their weight under
+    /// `distribution_mode` (their stake, by default), logs an
+    /// `Event::Distributed`, counts it in `lifetime_distributed` and runs the
+    /// `on_distribute` callback. With
+    /// `auto_finalize`, the first distribution after the window has closed
+    /// finalizes the contract.
+    pub fn distribute_rewards(&mut self) -> Vec<(String, u128)> {
+        if self.auto_finalize && !self.is_staking_open() {
+            self.lock_in_rewards();
+        }
+        let result = self.distribute();
+        self.count_distributed(result.total_distributed);
+        if let Some(callback) = self.on_distribute.0.as_mut() {
+            for (user, reward) in &result.rewards {
+                callback(user, *reward);
+            }
+        }
+        self.events.push(Event::Distributed {
+            total: result.total_distributed,
+        });
+        result.rewards
+    }
+
+    /// The distribution `distribute_rewards` would make now, with its totals,
+    /// without logging it or running the callback.
+    pub fn distribute(&self) -> DistributionResult {
+        let rewards = self.current_rewards();
+        let total_distributed = rewards.iter().map(|(_, reward)| reward).sum::<u128>();
+        DistributionResult {
+            rewards,
+            total_distributed,
+            leftover: self.total_coins.saturating_sub(total_distributed),
+            at: self.clock.now(),
+        }
+    }
+
+    /// Pays out `fraction_bps` of the remaining `total_coins` by the same
+    /// weights as `distribute_rewards` and deducts it from the pool, so
+    /// repeated calls release the pool gradually. Refused once the contract
+    /// is finalized, since its distribution is locked in. Only the owner may
+    /// release the pool this way.
+    pub fn distribute_fraction(
+        &mut self,
+        caller: &str,
+        fraction_bps: u16,
+    ) -> Result<Vec<(String, u128)>, StakingError> {
+        self.require_owner(caller)?;
+        if self.finalized {
+            return Err(StakingError::Finalized);
+        }
+        let fraction_bps = fraction_bps.min(10_000);
+        let amount = mul_div(self.total_coins, fraction_bps as u128, 10_000);
+        let rewards = self.split_amount(amount, self.effective_weights());
+        let total = rewards.iter().map(|(_, reward)| reward).sum::<u128>();
+        self.total_coins -= total;
+        self.lifetime_distributed = self.lifetime_distributed.saturating_add(total);
+        self.events.push(Event::Distributed { total });
+        Ok(rewards)
+    }
+
+    /// What `distribute_rewards` would pay out right now, without logging it.
+    /// Every staker appears in username order, with a reward of 0 if they
+    /// earn nothing.
+    fn current_rewards(&self) -> Vec<(String, u128)> {
+        if self.finalized {
+            return self.final_rewards.clone();
+        }
+        let rewards: BTreeMap<String, u128> = self
+            .split_bounded(self.effective_weights())
+            .into_iter()
+            .collect();
+        self.stakers
+            .keys()
+            .map(|user| (user.clone(), rewards.get(user).copied().unwrap_or(0)))
+            .collect()
+    }
+
+    /// `split_pool`, holding every reward between `min_reward_per_user` and
+    /// `max_reward_per_user`. A staker pushed outside those bounds is fixed
+    /// at the bound and the rest of the pool is split among the others by
+    /// weight, repeating until everyone is within bounds. Only when everyone
+    /// is capped is part of the pool left undistributed. A floor the pool
+    /// cannot cover for every staker is ignored.
+    fn split_bounded(&self, weights: Vec<(String, u128)>) -> Vec<(String, u128)> {
+        let cap = self.max_reward_per_user;
+        let floor = self
+            .min_reward_per_user
+            .filter(|_| self.floor_covered(weights.len()));
+        if cap.is_none() && floor.is_none() {
+            return self.split_pool(weights);
+        }
+        let mut fixed: BTreeMap<String, u128> = BTreeMap::new();
+        loop {
+            let remaining = self.total_coins.saturating_sub(
+                fixed
+                    .values()
+                    .fold(0u128, |sum, reward| sum.saturating_add(*reward)),
+            );
+            let free = weights
+                .iter()
+                .filter(|(user, _)| !fixed.contains_key(user))
+                .cloned()
+                .collect();
+            let shares: BTreeMap<String, u128> =
+                self.split_amount(remaining, free).into_iter().collect();
+            let mut bounded: Vec<(String, u128)> = cap
+                .map(|cap| {
+                    shares
+                        .iter()
+                        .filter(|(_, share)| **share > cap)
+                        .map(|(user, _)| (user.clone(), cap))
+                        .collect()
+                })
+                .unwrap_or_default();
+            if bounded.is_empty() {
+                if let Some(floor) = floor {
+                    bounded = shares
+                        .iter()
+                        .filter(|(_, share)| **share < floor)
+                        .map(|(user, _)| (user.clone(), floor))
+                        .collect();
+                }
+            }
+            if bounded.is_empty() {
+                return weights
+                    .into_iter()
+                    .map(|(user, _)| {
+                        let reward = fixed
+                            .get(&user)
+                            .or_else(|| shares.get(&user))
+                            .copied()
+                            .unwrap_or(0);
+                        (user, reward)
+                    })
+                    .collect();
+            }
+            fixed.extend(bounded);
+        }
+    }
+
+    /// `total_coins` minus what the current configuration commits to paying:
+    /// zero when the pool is paid out exactly, positive when some is left
+    /// over (e.g. everyone is capped) and negative when the pool cannot cover
+    /// `min_reward_per_user` for every staker.
+    pub fn distribution_balance(&self) -> i128 {
+        let distributed = self
+            .current_rewards()
+            .iter()
+            .map(|(_, reward)| reward)
+            .sum::<u128>();
+        let promised = self
+            .min_reward_per_user
+            .map_or(0, |floor| floor.saturating_mul(self.staker_count() as u128));
+        signed_difference(self.total_coins, distributed.max(promised))
+    }
+
+    /// Whether the pool can pay `min_reward_per_user` to `stakers` stakers.
+    fn floor_covered(&self, stakers: usize) -> bool {
+        self.min_reward_per_user.is_none_or(|floor| {
+            floor
+                .checked_mul(stakers as u128)
+                .is_some_and(|needed| needed <= self.total_coins)
+        })
+    }
+
+    /// Per-user change in proportional reward from snapshot `a` to `b`,
+    /// positive if the user would earn more in `b`. Users in only one
+    /// snapshot appear with their whole reward as the change. Both sides are
+    /// computed with floor rounding and ties settled by name.
+    pub fn distribution_diff(a: &Snapshot, b: &Snapshot) -> Vec<(String, i128)> {
+        let distribution = |snapshot: &Snapshot| -> BTreeMap<String, u128> {
+            let weights = snapshot
+                .stakers
+                .iter()
+                .map(|(user, entry)| (user.clone(), entry.amount as u128))
+                .collect();
+            split_proportionally(
+                snapshot.total_coins,
+                weights,
+                RoundingMode::Floor,
+                1,
+                TieBreak::ByName,
+            )
+            .into_iter()
+            .collect()
+        };
+        let (before, after) = (distribution(a), distribution(b));
+        before
+            .keys()
+            .chain(after.keys())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|user| {
+                let old = before.get(user).copied().unwrap_or(0);
+                let new = after.get(user).copied().unwrap_or(0);
+                (user.clone(), signed_difference(new, old))
+            })
+            .collect()
+    }
+
+    /// The proportional distribution as it stood when `snapshot` was taken,
+    /// ignoring the live stakers and pool.
+    pub fn distribute_as_of(&self, snapshot: &Snapshot) -> Vec<(String, u128)> {
+        self.split_amount(
+            snapshot.total_coins,
+            snapshot
+                .stakers
+                .iter()
+                .map(|(user, entry)| (user.clone(), entry.amount as u128))
+                .collect(),
+        )
+    }
+
+    /// Splits every pool in `reward_pools` among stakers in proportion to their
+    /// stake, keyed by token.
+    pub fn distribute_multi(&self) -> BTreeMap<String, Vec<(String, u128)>> {
+        let weights = self.stake_weights();
+        self.reward_pools
+            .iter()
+            .map(|(token, pool)| (token.clone(), self.split_amount(*pool, weights.clone())))
+            .collect()
+    }
+
+    /// Splits `total_coins` in proportion to `amount * seconds_staked`, counting
+    /// each stake's time up to `end`.
+    pub fn distribute_time_weighted_rewards(&self, end: DateTime<Utc>) -> Vec<(String, u128)> {
+        self.split_pool(self.time_weighted_weights(end))
+    }
+
+    /// Splits `total_coins` by each stake scaled with the fraction of the
+    /// staking window it was present for, up to `window_end`. A stake placed
+    /// at the start counts in full; one placed halfway through counts half.
+    pub fn distribute_prorated_rewards(&self, window_end: DateTime<Utc>) -> Vec<(String, u128)> {
+        self.split_pool(self.prorated_weights(window_end))
+    }
+
+    /// Splits `total_coins` by the integer square root of each stake, so large
+    /// stakes still earn more but with diminishing returns.
+    pub fn distribute_sqrt_weighted_rewards(&self) -> Vec<(String, u128)> {
+        self.split_pool(self.sqrt_weights())
+    }
+
+    /// Splits `total_coins` by stake scaled with the best multiplier among the
+    /// `reward_tiers` whose threshold the stake reaches; stakes below every
+    /// threshold count at 1x (10000 bps).
+    pub fn distribute_tiered_rewards(&self) -> Vec<(String, u128)> {
+        self.split_pool(self.tiered_weights())
+    }
+
+    /// Splits `total_coins` by `amount * (1 + lock_bonus)`, so longer lockups
+    /// earn a larger share.
+    pub fn distribute_lockup_rewards(&self) -> Vec<(String, u128)> {
+        self.split_pool(self.lockup_weights())
+    }
+
+    /// Bonus weight for a lockup: 100 bps per full week, capped at 10000 bps
+    /// (double weight).
+    pub fn lock_bonus_bps(lock: Duration) -> u64 {
+        (lock.num_weeks().max(0) as u64 * 100).min(10_000)
+    }
+
+    /// Splits `total_coins` by stake boosted `referral_bonus_bps` for every
+    /// active referral the staker has made.
+    pub fn distribute_with_referral_bonus(&self) -> Vec<(String, u128)> {
+        self.split_pool(self.referral_weights())
+    }
+
+    /// The weight each staker carries before the pool is split: from the
+    /// strategy set with `set_distribution_strategy` if there is one, else
+    /// from `distribution_mode`.
+    pub fn effective_weights(&self) -> Vec<(String, u128)> {
+        match &self.strategy.0 {
+            Some(strategy) => strategy.weights(self),
+            None => self.distribution_mode.weights(self),
+        }
+    }
+
+    /// Replaces `distribution_mode` with a custom weighting. Like the clock,
+    /// the strategy is shared between clones and is not serialized.
+    pub fn set_distribution_strategy(&mut self, strategy: Box<dyn DistributionStrategy>) {
+        self.strategy = CustomStrategy(Some(Arc::from(strategy)));
+    }
+
+    /// Goes back to weighting by `distribution_mode`.
+    pub fn clear_distribution_strategy(&mut self) {
+        self.strategy = CustomStrategy(None);
+    }
+
+    fn stake_weights(&self) -> Vec<(String, u128)> {
+        self.stakers
+            .iter()
+            .map(|(user, entry)| (user.clone(), entry.amount as u128))
+            .collect()
+    }
+
+    fn time_weighted_weights(&self, end: DateTime<Utc>) -> Vec<(String, u128)> {
+        self.stakers
+            .iter()
+            .map(|(user, entry)| {
+                let seconds = (end - entry.staked_at).num_seconds().max(0) as u128;
+                (user.clone(), entry.amount as u128 * seconds)
+            })
+            .collect()
+    }
+
+    fn prorated_weights(&self, window_end: DateTime<Utc>) -> Vec<(String, u128)> {
+        let window = self.staking_duration.num_milliseconds().max(0) as u128;
+        self.stakers
+            .iter()
+            .map(|(user, entry)| {
+                let present =
+                    ((window_end - entry.staked_at).num_milliseconds().max(0) as u128).min(window);
+                (user.clone(), entry.amount as u128 * present)
+            })
+            .collect()
+    }
+
+    fn sqrt_weights(&self) -> Vec<(String, u128)> {
+        self.stakers
+            .iter()
+            .map(|(user, entry)| (user.clone(), entry.amount.isqrt() as u128))
+            .collect()
+    }
+
+    fn tiered_weights(&self) -> Vec<(String, u128)> {
+        self.stakers
+            .iter()
+            .map(|(user, entry)| {
+                let multiplier_bps = self
+                    .reward_tiers
+                    .iter()
+                    .filter(|(threshold, _)| entry.amount >= *threshold)
+                    .map(|(_, multiplier_bps)| *multiplier_bps)
+                    .max()
+                    .unwrap_or(10_000);
+                (user.clone(), entry.amount as u128 * multiplier_bps as u128)
+            })
+            .collect()
+    }
+
+    fn lockup_weights(&self) -> Vec<(String, u128)> {
+        self.stakers
+            .iter()
+            .map(|(user, entry)| {
+                let multiplier_bps = 10_000 + Contract::lock_bonus_bps(entry.lock);
+                (user.clone(), entry.amount as u128 * multiplier_bps as u128)
+            })
+            .collect()
+    }
+
+    fn referral_weights(&self) -> Vec<(String, u128)> {
+        self.stakers
+            .iter()
+            .map(|(user, entry)| {
+                let bonus_bps = self.referral_bonus_bps as u128 * self.referral_count(user) as u128;
+                (user.clone(), entry.amount as u128 * (10_000 + bonus_bps))
+            })
+            .collect()
+    }
+
+    /// Splits `total_coins` in proportion to `weights`.
+    fn split_pool(&self, weights: Vec<(String, u128)>) -> Vec<(String, u128)> {
+        self.split_amount(self.total_coins, weights)
+    }
+
+    /// Splits `amount` in proportion to `weights`, rounding per
+    /// `rounding_mode`, handing out dust per `precision_scale` and settling
+    /// ties per `tie_break`.
+    fn split_amount(&self, amount: u128, weights: Vec<(String, u128)>) -> Vec<(String, u128)> {
+        split_proportionally(
+            amount,
+            weights,
+            self.rounding_mode,
+            self.precision_scale,
+            self.tie_break,
+        )
+    }
+
+    /// Renders `distribute_rewards` as CSV with a `user,reward` header.
+    /// Usernames containing commas, quotes or newlines are quoted.
+    pub fn distribution_to_csv(&self) -> String {
+        let mut csv = String::from("user,reward\n");
+        for (user, reward) in self.current_rewards() {
+            if user.contains([',', '"', '\n', '\r']) {
+                csv.push_str(&format!("\"{}\",{}\n", user.replace('"', "\"\""), reward));
+            } else {
+                csv.push_str(&format!("{},{}\n", user, reward));
+            }
+        }
+        csv
+    }
+
+    /// Credits each staker's current reward to their unclaimed balance. The
+    /// pool is accrued at most once per epoch; calling this again before
+    /// `advance_epoch` credits nothing.
+    pub fn accrue_rewards(&mut self) {
+        if self.accrued_epoch == Some(self.epoch) {
+            return;
+        }
+        self.accrued_epoch = Some(self.epoch);
+        for (user, reward) in self.current_rewards() {
+            let unclaimed = self.unclaimed.entry(user).or_insert(0);
+            *unclaimed = unclaimed.saturating_add(reward);
+        }
+    }
+
+    /// Pays out and zeroes `user`'s unclaimed balance; claiming again before
+    /// the next accrual returns 0.
+    pub fn claim(&mut self, user: &str) -> Result<u128, StakingError> {
+        let user = &self.normalize_user(user);
+        if !self.stakers.contains_key(user) && !self.unclaimed.contains_key(user) {
+            return Err(StakingError::UnknownStaker);
+        }
+        self.require_unfrozen(user)?;
+        Ok(self.unclaimed.remove(user).unwrap_or(0))
+    }
+
+    /// `user`'s share of the total stake in basis points (0–10000).
+    pub fn reward_share_bps(&self, user: &str) -> Option<u16> {
+        let user = &self.normalize_user(user);
+        let amount = self.stakers.get(user)?.amount;
+        let total_staked = self.total_staked();
+        if total_staked == 0 {
+            return None;
+        }
+        Some((amount as u128 * 10_000 / total_staked as u128) as u16)
+    }
+
+    /// The largest single stake as a share of `total_staked`, in basis points
+    /// (0 for an empty pool).
+    pub fn concentration_bps(&self) -> u16 {
+        let total_staked = self.total_staked();
+        if total_staked == 0 {
+            return 0;
+        }
+        let largest = self
+            .stakers
+            .values()
+            .map(|entry| entry.amount)
+            .max()
+            .unwrap_or(0);
+        (largest as u128 * 10_000 / total_staked as u128) as u16
+    }
+
+    /// Adds `bonus` tokens for the current stakers. Before finalization the
+    /// bonus simply grows `total_coins`; afterwards the distribution is fixed,
+    /// so the bonus is split by current stake and credited to `unclaimed`.
+    pub fn add_bonus(&mut self, bonus: u128) {
+        if !self.finalized {
+            self.total_coins = self.total_coins.saturating_add(bonus);
+            return;
+        }
+        let weights = self
+            .stakers
+            .iter()
+            .map(|(user, entry)| (user.clone(), entry.amount as u128))
+            .collect();
+        for (user, share) in self.split_amount(bonus, weights) {
+            let unclaimed = self.unclaimed.entry(user).or_insert(0);
+            *unclaimed = unclaimed.saturating_add(share);
+        }
+    }
+
+    /// Rolls `user`'s unclaimed balance into their stake and returns the amount
+    /// restaked. This is allowed after the staking window has closed, since
+    /// it compounds existing rewards rather than bringing in a new deposit,
+    /// but not once the contract is finalized. The user must still pass the
+    /// whitelist, and the restake is held within `max_total_stake`: only as
+    /// much as fits under the cap is restaked, the rest staying unclaimed,
+    /// and a full pool is refused with `CapExceeded`. Likewise a balance too
+    /// large for a stake restakes `u64::MAX` and leaves the rest unclaimed.
+    pub fn restake(&mut self, user: &str) -> Result<u64, StakingError> {
+        let user = &self.normalize_user(user);
+        if self.finalized {
+            return Err(StakingError::Finalized);
+        }
+        self.validate_user(user)?;
+        let capacity = self
+            .max_total_stake
+            .map_or(u64::MAX, |cap| cap.saturating_sub(self.total_staked()));
+        if capacity == 0
+            && self
+                .unclaimed
+                .get(user)
+                .is_some_and(|unclaimed| *unclaimed > 0)
+        {
+            return Err(StakingError::CapExceeded);
+        }
+        let unclaimed = self.claim(user)?;
+        let amount = u64::try_from(unclaimed).unwrap_or(u64::MAX).min(capacity);
+        if unclaimed > amount as u128 {
+            self.unclaimed
+                .insert(user.to_string(), unclaimed - amount as u128);
+        }
+        if amount > 0 {
+            let now = self.clock.now();
+            self.record_stake(user, amount, now);
+            self.credit_stake(user.to_string(), StakeEntry::deposit(amount, now));
+        }
+        Ok(amount)
+    }
+
+    /// Governance weight of `user`, equal to their stake.
+    pub fn voting_power(&self, user: &str) -> Option<u64> {
+        self.get_stake(user)
+    }
+
+    /// Governance weight of `user` as the integer square root of their stake,
+    /// which dampens the influence of large stakers.
+    pub fn quadratic_voting_power(&self, user: &str) -> Option<u64> {
+        self.voting_power(user).map(u64::isqrt)
+    }
+
+    /// Whether `voters` together hold at least `threshold_bps` of the total
+    /// stake. Each voter is counted once; unknown voters carry no weight.
+    pub fn quorum_reached(&self, voters: &[&str], threshold_bps: u16) -> bool {
+        let total_staked = self.total_staked();
+        if total_staked == 0 {
+            return false;
+        }
+        let votes = voters
+            .iter()
+            .map(|voter| self.normalize_user(voter))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .filter_map(|voter| self.voting_power(&voter))
+            .map(u128::from)
+            .sum::<u128>();
+        votes * 10_000 >= threshold_bps as u128 * total_staked as u128
+    }
+
+    /// `user`'s reward relative to their stake, annualized from a period of
+    /// `elapsed` over a 365-day year, in basis points (saturating at
+    /// `u16::MAX`).
+    pub fn apr_bps(&self, user: &str, elapsed: Duration) -> Option<u16> {
+        let stake = self.get_stake(user).filter(|stake| *stake > 0)?;
+        let elapsed_ms = elapsed.num_milliseconds();
+        if elapsed_ms <= 0 {
+            return None;
+        }
+        let reward = self.reward_of(user)?;
+        let year_ms = Duration::days(365).num_milliseconds() as u128;
+        let apr = reward
+            .checked_mul(10_000 * year_ms)
+            .map_or(u128::MAX, |scaled| {
+                scaled / (stake as u128 * elapsed_ms as u128)
+            });
+        Some(apr.min(u16::MAX as u128) as u16)
+    }
+
+    /// Each staker's stake, share of the total stake and projected reward,
+    /// sorted by username.
+    pub fn reward_schedule(&self) -> Vec<RewardRow> {
+        let rewards: BTreeMap<String, u128> = self.current_rewards().into_iter().collect();
+        self.stakers
+            .iter()
+            .map(|(user, entry)| RewardRow {
+                user: user.clone(),
+                stake: entry.amount,
+                share_bps: self.reward_share_bps(user).unwrap_or(0),
+                reward: rewards.get(user).copied().unwrap_or(0),
+            })
+            .collect()
+    }
+
+    /// Previews the reward a new staker of `amount` would receive if they
+    /// joined the current pool now.
+    pub fn preview_reward_for(&self, amount: u64) -> u128 {
+        let total_staked = self.total_staked() as u128 + amount as u128;
+        if total_staked == 0 {
+            return 0;
+        }
+        mul_div(self.total_coins, amount as u128, total_staked)
+    }
+
+    /// Previews the reward `user` would receive from `distribute_rewards`.
+    pub fn reward_of(&self, user: &str) -> Option<u128> {
+        let user = &self.normalize_user(user);
+        self.current_rewards()
+            .into_iter()
+            .find(|(staker, _)| staker == user)
+            .map(|(_, reward)| reward)
+    }
+}
+
+/// Splits `amount` in proportion to `weights`.
+///
+/// Shares are rounded per `rounding_mode`. Rounded down, any remainder left
+/// over goes to the heaviest weight, ties settled by `tie_break`. Rounded to
+/// nearest, or with a `precision_scale` above 1, tokens short go to the
+/// shares rounded down the furthest and tokens over are taken back from the
+/// shares rounded up the furthest. Fractions are then compared in units of
+/// `1 / precision_scale` (exactly without a scale), equal fractions going
+/// heaviest first.
+///
+/// Exactly the whole amount is always paid out. If every weight is zero,
+/// nothing is paid and each entry gets 0.
+fn split_proportionally(
+    amount: u128,
+    weights: Vec<(String, u128)>,
+    rounding_mode: RoundingMode,
+    precision_scale: u128,
+    tie_break: TieBreak,
+) -> Vec<(String, u128)> {
+    let total_weight = weights.iter().map(|(_, weight)| weight).sum::<u128>();
+    if total_weight == 0 {
+        return weights.into_iter().map(|(user, _)| (user, 0)).collect();
+    }
+    // How far each reward falls short of its exact share after rounding:
+    // positive when rounded down, negative when rounded up. It is measured
+    // in units of 1 / precision_scale, or exactly without a scale.
+    let measure = |fraction: u128| -> i128 {
+        if precision_scale > 1 {
+            mul_div(precision_scale, fraction, total_weight) as i128
+        } else {
+            fraction as i128
+        }
+    };
+    let mut shortfalls: Vec<i128> = Vec::with_capacity(weights.len());
+    // Rounded-down shares never sum past `amount`; the shares rounded up
+    // are counted separately so the total cannot overflow.
+    let mut floors: u128 = 0;
+    let mut rounded_up: u128 = 0;
+    let mut rewards: Vec<(String, u128)> = weights
+        .iter()
+        .map(|(user, weight)| {
+            let (mut reward, remainder) = mul_div_rem(amount, *weight, total_weight);
+            floors += reward;
+            if rounding_mode == RoundingMode::Nearest && remainder >= total_weight - remainder {
+                reward += 1;
+                rounded_up += 1;
+                shortfalls.push(-measure(total_weight - remainder));
+            } else {
+                shortfalls.push(measure(remainder));
+            }
+            (user.clone(), reward)
+        })
+        .collect();
+    let unallocated = amount - floors;
+    let mut heaviest_first: Vec<usize> = (0..weights.len()).collect();
+    heaviest_first.sort_by(|a, b| {
+        let ((a_user, a_weight), (b_user, b_weight)) = (&weights[*a], &weights[*b]);
+        b_weight
+            .cmp(a_weight)
+            .then_with(|| tie_break.order(a_user, b_user))
+    });
+    if rounded_up > unallocated {
+        // Take the excess back from the rewards rounded up the furthest.
+        let mut largest_excess_first = heaviest_first;
+        largest_excess_first.sort_by_key(|index| shortfalls[*index]);
+        let mut excess = rounded_up - unallocated;
+        for index in largest_excess_first.into_iter().cycle() {
+            if rewards[index].1 > 0 {
+                rewards[index].1 -= 1;
+                excess -= 1;
+            }
+            if excess == 0 {
+                break;
+            }
+        }
+        return rewards;
+    }
+    let mut dust = unallocated - rounded_up;
+    if dust == 0 {
+        return rewards;
+    }
+    if rounding_mode == RoundingMode::Floor && precision_scale <= 1 {
+        rewards[heaviest_first[0]].1 += dust;
+        return rewards;
+    }
+    // Hand the rest to the rewards rounded down the furthest.
+    let mut largest_shortfall_first = heaviest_first;
+    largest_shortfall_first.sort_by(|a, b| shortfalls[*b].cmp(&shortfalls[*a]));
+    for index in largest_shortfall_first.into_iter().cycle() {
+        rewards[index].1 += 1;
+        dust -= 1;
+        if dust == 0 {
+            break;
+        }
+    }
+    rewards
+}
+
+/// `at + duration`, saturating at the bounds of `DateTime<Utc>`, so a
+/// duration too long to represent means "never" rather than overflowing.
+fn add_saturating(at: DateTime<Utc>, duration: Duration) -> DateTime<Utc> {
+    at.checked_add_signed(duration)
+        .unwrap_or(if duration < Duration::zero() {
+            DateTime::<Utc>::MIN_UTC
+        } else {
+            DateTime::<Utc>::MAX_UTC
+        })
+}
+
+/// `a - b` as a signed value, saturating at the bounds of i128.
+fn signed_difference(a: u128, b: u128) -> i128 {
+    if a >= b {
+        i128::try_from(a - b).unwrap_or(i128::MAX)
+    } else {
+        i128::try_from(b - a).map_or(i128::MIN, |shortfall| -shortfall)
+    }
+}
+
+/// `value * numerator / denominator` for `numerator <= denominator`, rounded
+/// down, without overflowing u128.
+fn mul_div(value: u128, numerator: u128, denominator: u128) -> u128 {
+    mul_div_rem(value, numerator, denominator).0
+}
+
+/// The quotient and remainder of `value * numerator / denominator` for
+/// `numerator <= denominator`. The product is taken at 256 bits, so the
+/// result is exact for every input.
+fn mul_div_rem(value: u128, numerator: u128, denominator: u128) -> (u128, u128) {
+    if let Some(product) = value.checked_mul(numerator) {
+        return (product / denominator, product % denominator);
+    }
+    let (high, low) = widening_mul(value, numerator);
+    // `high < denominator` because `numerator <= denominator`, so the
+    // quotient fits in u128: divide the low half in one bit at a time.
+    let (mut quotient, mut remainder) = (0u128, high);
+    for bit in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((low >> bit) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= denominator {
+            remainder = remainder.wrapping_sub(denominator);
+            quotient |= 1;
+        }
+    }
+    (quotient, remainder)
+}
+
+/// The full 256-bit product of `a` and `b` as `(high, low)` halves.
+fn widening_mul(a: u128, b: u128) -> (u128, u128) {
+    const LOW: u128 = u64::MAX as u128;
+    let (a_high, a_low) = (a >> 64, a & LOW);
+    let (b_high, b_low) = (b >> 64, b & LOW);
+    let low_low = a_low * b_low;
+    let low_high = a_low * b_high;
+    let high_low = a_high * b_low;
+    let middle = (low_low >> 64) + (low_high & LOW) + (high_low & LOW);
+    let low = (low_low & LOW) | (middle << 64);
+    let high = a_high * b_high + (low_high >> 64) + (high_low >> 64) + (middle >> 64);
+    (high, low)
+}
+
+/// Configures a `Contract` option by option; unset options keep the defaults
+/// of `Contract::new`.
+pub struct ContractBuilder {
+    total_coins: u128,
+    staking_duration: Duration,
+    min_stake: u64,
+    max_total_stake: Option<u64>,
+    early_withdraw_penalty_bps: u16,
+    min_stakers_for_distribution: usize,
+    reward_tiers: Vec<(u64, u16)>,
+    normalize_usernames: bool,
+    stake_fee_bps: u16,
+    slash_to_pool: bool,
+    owner: String,
+    clock: Option<Box<dyn Clock>>,
+}
+
+impl Default for ContractBuilder {
+    fn default() -> Self {
+        ContractBuilder {
+            total_coins: 0,
+            staking_duration: Duration::days(7),
+            min_stake: 0,
+            max_total_stake: None,
+            early_withdraw_penalty_bps: 0,
+            min_stakers_for_distribution: 0,
+            reward_tiers: vec![],
+            normalize_usernames: false,
+            stake_fee_bps: 0,
+            slash_to_pool: false,
+            owner: String::new(),
+            clock: None,
+        }
+    }
+}
+
+impl ContractBuilder {
+    pub fn new() -> Self {
+        ContractBuilder::default()
+    }
+
+    pub fn total_coins(mut self, total_coins: u128) -> Self {
+        self.total_coins = total_coins;
+        self
+    }
+
+    pub fn staking_duration(mut self, duration: Duration) -> Self {
+        self.staking_duration = duration;
+        self
+    }
+
+    pub fn min_stake(mut self, min_stake: u64) -> Self {
+        self.min_stake = min_stake;
+        self
+    }
+
+    pub fn max_total_stake(mut self, cap: u64) -> Self {
+        self.max_total_stake = Some(cap);
+        self
+    }
+
+    /// Capped at 10000 bps, the whole amount.
+    pub fn early_withdraw_penalty_bps(mut self, penalty_bps: u16) -> Self {
+        self.early_withdraw_penalty_bps = penalty_bps.min(10_000);
+        self
+    }
+
+    pub fn min_stakers_for_distribution(mut self, min_stakers: usize) -> Self {
+        self.min_stakers_for_distribution = min_stakers;
+        self
+    }
+
+    pub fn reward_tiers(mut self, tiers: Vec<(u64, u16)>) -> Self {
+        self.reward_tiers = tiers;
+        self
+    }
+
+    pub fn normalize_usernames(mut self, normalize: bool) -> Self {
+        self.normalize_usernames = normalize;
+        self
+    }
+
+    /// Capped at 10000 bps, the whole deposit.
+    pub fn stake_fee_bps(mut self, fee_bps: u16) -> Self {
+        self.stake_fee_bps = fee_bps.min(10_000);
+        self
+    }
+
+    pub fn slash_to_pool(mut self, slash_to_pool: bool) -> Self {
+        self.slash_to_pool = slash_to_pool;
+        self
+    }
+
+    pub fn owner(mut self, owner: String) -> Self {
+        self.owner = owner;
+        self
+    }
+
+    pub fn clock(mut self, clock: Box<dyn Clock>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Builds the contract. Without the `std` feature a clock must have been
+    /// supplied with `clock`.
+    pub fn build(self) -> Contract {
+        #[cfg(feature = "std")]
+        let clock = self.clock.unwrap_or_else(|| Box::new(SystemClock));
+        #[cfg(not(feature = "std"))]
+        let clock = self
+            .clock
+            .expect("a clock is required without the std feature");
+        let mut contract = Contract::new_with_clock(self.total_coins, self.staking_duration, clock);
+        contract.min_stake = self.min_stake;
+        contract.max_total_stake = self.max_total_stake;
+        contract.early_withdraw_penalty_bps = self.early_withdraw_penalty_bps;
+        contract.min_stakers_for_distribution = self.min_stakers_for_distribution;
+        contract.reward_tiers = self.reward_tiers;
+        contract.normalize_usernames = self.normalize_usernames;
+        contract.stake_fee_bps = self.stake_fee_bps;
+        contract.slash_to_pool = self.slash_to_pool;
+        contract.owner = self.owner;
+        contract
+    }
+}
+
+/// A `Contract` shared between threads. Every call locks the contract for
+/// its duration; a poisoned lock is reported as `StakingError::Locked`.
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub struct SharedContract {
+    inner: Arc<Mutex<Contract>>,
+}
+
+#[cfg(feature = "std")]
+impl SharedContract {
+    pub fn new(contract: Contract) -> Self {
+        SharedContract {
+            inner: Arc::new(Mutex::new(contract)),
+        }
+    }
+
+    pub fn stake(&self, user: String, amount: u64) -> Result<(), StakingError> {
+        self.lock()?.try_stake(user, amount)
+    }
+
+    pub fn unstake(&self, user: &str, amount: u64) -> Result<u64, StakingError> {
+        self.lock()?.unstake(user, amount)
+    }
+
+    pub fn distribute_rewards(&self) -> Result<Vec<(String, u128)>, StakingError> {
+        Ok(self.lock()?.distribute_rewards())
+    }
+
+    pub fn staker_count(&self) -> Result<usize, StakingError> {
+        Ok(self.lock()?.staker_count())
+    }
+
+    pub fn total_staked(&self) -> Result<u64, StakingError> {
+        Ok(self.lock()?.total_staked())
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Contract>, StakingError> {
+        self.inner.lock().map_err(|_| StakingError::Locked)
+    }
+}
+
+impl fmt::Display for Contract {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Pool: {} coins | {} stakers | {} staked | {}",
+            self.total_coins,
+            self.staker_count(),
+            self.total_staked(),
+            if self.is_staking_open() {
+                "OPEN"
+            } else {
+                "CLOSED"
+            }
+        )
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use cool_asserts::assert_panics;
+    use std::panic::AssertUnwindSafe;
+
+    #[test]
+    fn test_contract_creation() {
+        let contract = Contract::new(1_000_000);
+        assert_eq!(contract.total_coins, 1_000_000);
+        assert_eq!(contract.stakers.len(), 0);
+    }
+
+    #[test]
+    fn test_contract_staking() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        assert_eq!(contract.stakers.get("Alice").unwrap().amount, 5_000);
+        contract.stake(String::from("Alice"), 3_000);
+        assert_eq!(contract.stakers.get("Alice").unwrap().amount, 8_000);
+    }
+
+    #[test]
+    fn test_contract_staking_accumulates() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 1_000);
+        contract.stake(String::from("Alice"), 3_000);
+        assert_eq!(contract.stakers.len(), 2);
+        assert_eq!(contract.stakers.get("Alice").unwrap().amount, 8_000);
+        assert_eq!(contract.stakers.get("Bob").unwrap().amount, 1_000);
+    }
+
+    #[test]
+    fn test_contract_staking_after_seven_days() {
+        let clock = MockClock::new(Utc::now());
+        let mut contract =
+            Contract::new_with_clock(1_000_000, Duration::days(7), Box::new(clock.clone()));
+        contract.stake(String::from("Alice"), 5_000);
+        assert_eq!(contract.stakers.get("Alice").unwrap().amount, 5_000);
+
+        // This is to simulate the passage of seven days
+        clock.advance(Duration::days(7));
+
+        // This prepares the arguments for the stake method
+        let user = String::from("Bob");
+        let amount = 20_000;
+
+        // This actually performs the assertion
+        let contract = AssertUnwindSafe(contract);
+        assert_panics!({
+            let mut contract = contract;
+            contract.stake(user, amount)
+        });
+    }
+
+    #[test]
+    fn test_contract_distribute_rewards() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        let rewards = contract.distribute_rewards();
+        assert_eq!(rewards.len(), 2);
+        assert_eq!(rewards[0], ("Alice".to_string(), 200_000));
+        assert_eq!(rewards[1], ("Bob".to_string(), 800_000));
+    }
+
+    #[test]
+    fn test_distribute_rewards_empty() {
+        let mut contract = Contract::new(1_000_000);
+        assert_eq!(contract.distribute_rewards(), vec![]);
+    }
+
+    #[test]
+    fn test_distribute_rewards_large_pool() {
+        let mut contract = Contract::new(1_000_000_000_000_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        let rewards = contract.distribute_rewards();
+        assert_eq!(rewards[0], ("Alice".to_string(), 200_000_000_000_000_000));
+        assert_eq!(rewards[1], ("Bob".to_string(), 800_000_000_000_000_000));
+    }
+
+    #[test]
+    fn test_try_stake() {
+        let mut contract = Contract::new(1_000_000);
+        assert_eq!(contract.try_stake(String::from("Alice"), 5_000), Ok(()));
+        assert_eq!(contract.stakers.get("Alice").unwrap().amount, 5_000);
+    }
+
+    #[test]
+    fn test_try_stake_window_closed() {
+        let mut contract = Contract::new(1_000_000);
+        contract.start_date = Utc::now() - Duration::days(7);
+        assert_eq!(
+            contract.try_stake(String::from("Alice"), 5_000),
+            Err(StakingError::WindowClosed)
+        );
+        assert!(contract.stakers.is_empty());
+    }
+
+    #[test]
+    fn test_contract_custom_duration() {
+        let mut contract = Contract::new_with_duration(1_000_000, Duration::days(1));
+        assert_eq!(contract.staking_duration, Duration::days(1));
+
+        contract.start_date = Utc::now() - Duration::hours(23);
+        assert_eq!(contract.try_stake(String::from("Alice"), 5_000), Ok(()));
+
+        contract.start_date = Utc::now() - Duration::days(1);
+        assert_eq!(
+            contract.try_stake(String::from("Bob"), 5_000),
+            Err(StakingError::WindowClosed)
+        );
+    }
+
+    #[test]
+    fn test_try_stake_with_mock_clock() {
+        let clock = MockClock::new(Utc::now());
+        let mut contract =
+            Contract::new_with_clock(1_000_000, Duration::days(7), Box::new(clock.clone()));
+
+        clock.advance(Duration::days(6));
+        assert_eq!(contract.try_stake(String::from("Alice"), 5_000), Ok(()));
+
+        clock.advance(Duration::days(1));
+        assert_eq!(
+            contract.try_stake(String::from("Bob"), 5_000),
+            Err(StakingError::WindowClosed)
+        );
+        assert_eq!(contract.stakers.len(), 1);
+    }
+
+    #[test]
+    fn test_unstake_partial() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        assert_eq!(contract.unstake("Alice", 2_000), Ok(2_000));
+        assert_eq!(contract.stakers.get("Alice").unwrap().amount, 3_000);
+    }
+
+    #[test]
+    fn test_unstake_full_removes_entry() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        assert_eq!(contract.unstake("Alice", 5_000), Ok(5_000));
+        assert!(!contract.stakers.contains_key("Alice"));
+    }
+
+    #[test]
+    fn test_unstake_insufficient_stake() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        assert_eq!(
+            contract.unstake("Alice", 5_001),
+            Err(StakingError::InsufficientStake)
+        );
+        assert_eq!(contract.stakers.get("Alice").unwrap().amount, 5_000);
+    }
+
+    #[test]
+    fn test_unstake_unknown_staker() {
+        let mut contract = Contract::new(1_000_000);
+        assert_eq!(
+            contract.unstake("Alice", 1),
+            Err(StakingError::UnknownStaker)
+        );
+    }
+
+    #[test]
+    fn test_distribute_rewards_sorted_by_user() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Dave"), 1_000);
+        contract.stake(String::from("Alice"), 4_000);
+        contract.stake(String::from("Carol"), 3_000);
+        contract.stake(String::from("Bob"), 2_000);
+        let users: Vec<String> = contract
+            .distribute_rewards()
+            .into_iter()
+            .map(|(user, _)| user)
+            .collect();
+        assert_eq!(users, vec!["Alice", "Bob", "Carol", "Dave"]);
+    }
+
+    #[test]
+    fn test_distribute_rewards_assigns_dust() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 1_000);
+        contract.stake(String::from("Bob"), 1_000);
+        contract.stake(String::from("Carol"), 1_000);
+        let rewards = contract.distribute_rewards();
+        assert_eq!(
+            rewards.iter().map(|(_, reward)| reward).sum::<u128>(),
+            1_000_000
+        );
+        assert_eq!(rewards[0], ("Alice".to_string(), 333_334));
+        assert_eq!(rewards[1], ("Bob".to_string(), 333_333));
+        assert_eq!(rewards[2], ("Carol".to_string(), 333_333));
+    }
+
+    #[test]
+    fn test_distribute_rewards_dust_goes_to_largest_staker() {
+        let mut contract = Contract::new(100);
+        contract.stake(String::from("Alice"), 1);
+        contract.stake(String::from("Bob"), 5);
+        contract.stake(String::from("Carol"), 1);
+        let rewards = contract.distribute_rewards();
+        assert_eq!(rewards.iter().map(|(_, reward)| reward).sum::<u128>(), 100);
+        assert_eq!(rewards[0], ("Alice".to_string(), 14));
+        assert_eq!(rewards[1], ("Bob".to_string(), 72));
+        assert_eq!(rewards[2], ("Carol".to_string(), 14));
+    }
+
+    #[test]
+    fn test_reward_of() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        assert_eq!(contract.reward_of("Alice"), Some(200_000));
+        assert_eq!(contract.reward_of("Bob"), Some(800_000));
+        assert_eq!(contract.stakers.len(), 2);
+    }
+
+    #[test]
+    fn test_reward_of_unknown_staker() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        assert_eq!(contract.reward_of("Bob"), None);
+    }
+
+    #[test]
+    fn test_reward_of_empty_pool() {
+        let contract = Contract::new(1_000_000);
+        assert_eq!(contract.reward_of("Alice"), None);
+    }
+
+    #[test]
+    fn test_distribute_time_weighted_rewards() {
+        let clock = MockClock::new(Utc::now());
+        let mut contract =
+            Contract::new_with_clock(1_000_000, Duration::days(7), Box::new(clock.clone()));
+        let end = contract.start_date + Duration::days(7);
+        contract.stake(String::from("Alice"), 5_000);
+        clock.advance(Duration::days(3));
+        contract.stake(String::from("Bob"), 5_000);
+
+        let rewards = contract.distribute_time_weighted_rewards(end);
+        assert_eq!(rewards[0], ("Alice".to_string(), 636_364));
+        assert_eq!(rewards[1], ("Bob".to_string(), 363_636));
+        assert!(rewards[0].1 > rewards[1].1);
+    }
+
+    #[test]
+    fn test_stake_top_up_averages_staked_at() {
+        let clock = MockClock::new(Utc::now());
+        let mut contract =
+            Contract::new_with_clock(1_000_000, Duration::days(7), Box::new(clock.clone()));
+        let start = contract.start_date;
+        contract.stake(String::from("Alice"), 1_000);
+        clock.advance(Duration::days(4));
+        contract.stake(String::from("Alice"), 3_000);
+
+        let entry = contract.stakers.get("Alice").unwrap();
+        assert_eq!(entry.amount, 4_000);
+        assert_eq!(entry.staked_at, start + Duration::days(3));
+    }
+
+    #[test]
+    fn test_total_staked_and_staker_count() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        contract.stake(String::from("Carol"), 1_000);
+        assert_eq!(contract.total_staked(), 26_000);
+        assert_eq!(contract.staker_count(), 3);
+    }
+
+    #[test]
+    fn test_total_staked_fresh_contract() {
+        let contract = Contract::new(1_000_000);
+        assert_eq!(contract.total_staked(), 0);
+        assert_eq!(contract.staker_count(), 0);
+    }
+
+    #[test]
+    fn test_min_stake_below() {
+        let mut contract = Contract::new_with_min_stake(1_000_000, 1_000);
+        assert_eq!(
+            contract.try_stake(String::from("Alice"), 999),
+            Err(StakingError::BelowMinimum)
+        );
+        assert!(!contract.stakers.contains_key("Alice"));
+    }
+
+    #[test]
+    fn test_min_stake_equal_and_above() {
+        let mut contract = Contract::new_with_min_stake(1_000_000, 1_000);
+        assert_eq!(contract.try_stake(String::from("Alice"), 1_000), Ok(()));
+        assert_eq!(contract.try_stake(String::from("Bob"), 1_001), Ok(()));
+        assert_eq!(contract.stakers.get("Alice").unwrap().amount, 1_000);
+        assert_eq!(contract.stakers.get("Bob").unwrap().amount, 1_001);
+    }
+
+    #[test]
+    fn test_zero_stake_rejected() {
+        let mut contract = Contract::new(1_000_000);
+        assert_eq!(
+            contract.try_stake(String::from("Alice"), 0),
+            Err(StakingError::ZeroAmount)
+        );
+        assert!(contract.stakers.is_empty());
+
+        let contract = AssertUnwindSafe(contract);
+        assert_panics!(
+            {
+                let mut contract = contract;
+                contract.stake(String::from("Alice"), 0)
+            },
+            includes("zero amount")
+        );
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut contract = Contract::new_with_min_stake(1_000_000, 100);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+
+        let json = contract.to_json();
+        let mut restored = Contract::from_json(&json).unwrap();
+        assert_eq!(restored, contract);
+        assert_eq!(restored.to_json(), json);
+        assert_eq!(restored.total_coins, 1_000_000);
+        assert_eq!(restored.start_date, contract.start_date);
+        assert_eq!(restored.staking_duration, Duration::days(7));
+        assert_eq!(restored.min_stake, 100);
+        assert_eq!(restored.stakers.get("Alice").unwrap().amount, 5_000);
+        assert_eq!(
+            restored.stakers.get("Bob").unwrap().staked_at,
+            contract.stakers.get("Bob").unwrap().staked_at
+        );
+        assert_eq!(restored.distribute_rewards(), contract.distribute_rewards());
+    }
+
+    #[test]
+    fn test_from_json_invalid() {
+        assert!(Contract::from_json("{\"total_coins\": 1}").is_err());
+    }
+
+    #[test]
+    fn test_accrue_and_claim() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        contract.accrue_rewards();
+        assert_eq!(contract.unclaimed.get("Alice"), Some(&200_000));
+
+        assert_eq!(contract.claim("Alice"), Ok(200_000));
+        assert_eq!(contract.claim("Alice"), Ok(0));
+        assert_eq!(contract.unclaimed.get("Bob"), Some(&800_000));
+    }
+
+    #[test]
+    fn test_accrue_rewards_once_per_epoch() {
+        let mut contract = Contract::new_with_owner(1_000_000, String::from("admin"));
+        contract.stake(String::from("Alice"), 5_000);
+        for _ in 0..5 {
+            contract.accrue_rewards();
+        }
+        assert_eq!(contract.claim("Alice"), Ok(1_000_000));
+
+        contract.accrue_rewards();
+        assert_eq!(contract.claim("Alice"), Ok(0));
+
+        contract.advance_epoch("admin", 500_000).unwrap();
+        contract.accrue_rewards();
+        contract.accrue_rewards();
+        assert_eq!(contract.claim("Alice"), Ok(500_000));
+    }
+
+    #[test]
+    fn test_claim_unknown_staker() {
+        let mut contract = Contract::new(1_000_000);
+        assert_eq!(contract.claim("Alice"), Err(StakingError::UnknownStaker));
+    }
+
+    #[test]
+    fn test_remaining_window() {
+        let start = Utc
+            .with_ymd_and_hms(2024, 2, 1, 0, 0, 0)
+            .single()
+            .expect("Invalid date");
+        let clock = MockClock::new(start);
+        let contract =
+            Contract::new_with_clock(1_000_000, Duration::days(7), Box::new(clock.clone()));
+        assert_eq!(contract.remaining_window(), Duration::days(7));
+
+        clock.advance(Duration::days(5));
+        assert_eq!(contract.remaining_window(), Duration::days(2));
+
+        clock.advance(Duration::days(3));
+        assert_eq!(contract.remaining_window(), Duration::zero());
+    }
+
+    #[test]
+    fn test_is_staking_open() {
+        let mut contract = Contract::new(1_000_000);
+        assert!(contract.is_staking_open());
+
+        contract.start_date = Utc::now() - Duration::days(8);
+        assert!(!contract.is_staking_open());
+    }
+
+    #[test]
+    fn test_max_total_stake() {
+        let mut contract = Contract::new(1_000_000);
+        contract.max_total_stake = Some(10_000);
+        assert_eq!(contract.try_stake(String::from("Alice"), 4_000), Ok(()));
+        assert_eq!(contract.try_stake(String::from("Bob"), 4_000), Ok(()));
+        assert_eq!(contract.total_staked(), 8_000);
+
+        assert_eq!(
+            contract.try_stake(String::from("Carol"), 2_001),
+            Err(StakingError::CapExceeded)
+        );
+        assert!(!contract.stakers.contains_key("Carol"));
+
+        assert_eq!(contract.try_stake(String::from("Carol"), 2_000), Ok(()));
+        assert_eq!(contract.total_staked(), 10_000);
+        assert_eq!(
+            contract.try_stake(String::from("Alice"), 1),
+            Err(StakingError::CapExceeded)
+        );
+    }
+
+    #[test]
+    fn test_contract_clone_and_eq() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+
+        let mut snapshot = contract.clone();
+        assert_eq!(snapshot, contract);
+
+        snapshot.stake(String::from("Carol"), 1_000);
+        assert_ne!(snapshot, contract);
+        assert_eq!(contract.staker_count(), 2);
+    }
+
+    #[test]
+    fn test_contract_display() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        assert_eq!(
+            contract.to_string(),
+            "Pool: 1000000 coins | 2 stakers | 25000 staked | OPEN"
+        );
+
+        contract.start_date = Utc::now() - Duration::days(8);
+        let summary = contract.to_string();
+        assert!(summary.contains("2 stakers"));
+        assert!(summary.ends_with("CLOSED"));
+    }
+
+    #[test]
+    fn test_ranked_stakers() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 1_000);
+        contract.stake(String::from("Bob"), 3_000);
+        contract.stake(String::from("Carol"), 2_000);
+        assert_eq!(
+            contract.ranked_stakers(),
+            vec![
+                ("Bob".to_string(), 3_000),
+                ("Carol".to_string(), 2_000),
+                ("Alice".to_string(), 1_000),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ranked_stakers_tie_break() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Dave"), 2_000);
+        contract.stake(String::from("Bob"), 2_000);
+        contract.stake(String::from("Alice"), 1_000);
+        assert_eq!(
+            contract.ranked_stakers(),
+            vec![
+                ("Bob".to_string(), 2_000),
+                ("Dave".to_string(), 2_000),
+                ("Alice".to_string(), 1_000),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_transfer_stake_full() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 1_000);
+        assert_eq!(
+            contract.transfer_stake("Alice", String::from("Bob"), 5_000),
+            Ok(())
+        );
+        assert!(!contract.stakers.contains_key("Alice"));
+        assert_eq!(contract.stakers.get("Bob").unwrap().amount, 6_000);
+    }
+
+    #[test]
+    fn test_transfer_stake_partial() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.start_date = Utc::now() - Duration::days(8);
+        assert_eq!(
+            contract.transfer_stake("Alice", String::from("Carol"), 2_000),
+            Ok(())
+        );
+        assert_eq!(contract.stakers.get("Alice").unwrap().amount, 3_000);
+        assert_eq!(contract.stakers.get("Carol").unwrap().amount, 2_000);
+        assert_eq!(
+            contract.stakers.get("Carol").unwrap().staked_at,
+            contract.stakers.get("Alice").unwrap().staked_at
+        );
+        assert_eq!(contract.total_staked(), 5_000);
+    }
+
+    #[test]
+    fn test_transfer_stake_errors() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        assert_eq!(
+            contract.transfer_stake("Alice", String::from("Bob"), 5_001),
+            Err(StakingError::InsufficientStake)
+        );
+        assert_eq!(
+            contract.transfer_stake("Carol", String::from("Bob"), 1),
+            Err(StakingError::UnknownStaker)
+        );
+        assert_eq!(
+            contract.transfer_stake("Alice", String::from("Bob"), 0),
+            Err(StakingError::ZeroAmount)
+        );
+        assert_eq!(contract.stakers.get("Alice").unwrap().amount, 5_000);
+        assert!(!contract.stakers.contains_key("Bob"));
+    }
+
+    #[test]
+    fn test_slash_partial() {
+        let mut contract = Contract::new_with_owner(1_000_000, String::from("admin"));
+        contract.stake(String::from("Alice"), 5_000);
+        assert_eq!(contract.slash("admin", "Alice", 2_000), Ok(2_000));
+        assert_eq!(contract.stakers.get("Alice").unwrap().amount, 3_000);
+        assert_eq!(contract.total_coins, 1_000_000);
+    }
+
+    #[test]
+    fn test_slash_full_caps_at_balance() {
+        let mut contract = Contract::new_with_owner(1_000_000, String::from("admin"));
+        contract.slash_to_pool = true;
+        contract.stake(String::from("Alice"), 5_000);
+        assert_eq!(contract.slash("admin", "Alice", 9_000), Ok(5_000));
+        assert!(!contract.stakers.contains_key("Alice"));
+        assert_eq!(contract.total_coins, 1_005_000);
+    }
+
+    #[test]
+    fn test_slash_unknown_staker() {
+        let mut contract = Contract::new_with_owner(1_000_000, String::from("admin"));
+        assert_eq!(
+            contract.slash("admin", "Alice", 1_000),
+            Err(StakingError::UnknownStaker)
+        );
+    }
+
+    #[test]
+    fn test_finalize_blocks_staking() {
+        let mut contract = Contract::new_with_owner(1_000_000, String::from("admin"));
+        contract.stake(String::from("Alice"), 5_000);
+        contract.finalize("admin").unwrap();
+        assert!(contract.is_finalized());
+        assert_eq!(
+            contract.try_stake(String::from("Bob"), 5_000),
+            Err(StakingError::Finalized)
+        );
+        assert_eq!(contract.staker_count(), 1);
+    }
+
+    #[test]
+    fn test_finalize_locks_distribution() {
+        let mut contract = Contract::new_with_owner(1_000_000, String::from("admin"));
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        let before = contract.distribute_rewards();
+        contract.finalize("admin").unwrap();
+
+        contract
+            .transfer_stake("Bob", String::from("Alice"), 20_000)
+            .unwrap();
+        contract.total_coins = 2_000_000;
+        assert_eq!(contract.distribute_rewards(), before);
+
+        contract.finalize("admin").unwrap();
+        assert_eq!(contract.distribute_rewards(), before);
+    }
+
+    #[test]
+    fn test_batch_stake() {
+        let mut contract = Contract::new(1_000_000);
+        let entries = vec![
+            (String::from("Alice"), 5_000),
+            (String::from("Bob"), 20_000),
+            (String::from("Alice"), 1_000),
+        ];
+        assert_eq!(contract.batch_stake(entries), Ok(()));
+        assert_eq!(contract.stakers.get("Alice").unwrap().amount, 6_000);
+        assert_eq!(contract.stakers.get("Bob").unwrap().amount, 20_000);
+    }
+
+    #[test]
+    fn test_batch_stake_rejects_whole_batch() {
+        let mut contract = Contract::new_with_min_stake(1_000_000, 100);
+        contract.stake(String::from("Dave"), 1_000);
+        let before = contract.clone();
+        let entries = vec![
+            (String::from("Alice"), 5_000),
+            (String::from("Bob"), 0),
+            (String::from("Carol"), 50),
+        ];
+        assert_eq!(contract.batch_stake(entries), Err(StakingError::ZeroAmount));
+        assert_eq!(contract, before);
+    }
+
+    #[test]
+    fn test_batch_stake_checks_cap_across_entries() {
+        let mut contract = Contract::new(1_000_000);
+        contract.max_total_stake = Some(10_000);
+        let entries = vec![(String::from("Alice"), 6_000), (String::from("Bob"), 6_000)];
+        assert_eq!(
+            contract.batch_stake(entries),
+            Err(StakingError::CapExceeded)
+        );
+        assert!(contract.stakers.is_empty());
+    }
+
+    #[test]
+    fn test_unstake_early_withdraw_penalty() {
+        let mut contract = Contract::new(1_000_000);
+        contract.early_withdraw_penalty_bps = 500;
+        contract.stake(String::from("Alice"), 10_000);
+        assert_eq!(contract.unstake("Alice", 4_000), Ok(3_800));
+        assert_eq!(contract.stakers.get("Alice").unwrap().amount, 6_000);
+        assert_eq!(contract.total_coins, 1_000_200);
+    }
+
+    #[test]
+    fn test_unstake_no_penalty_after_window() {
+        let mut contract = Contract::new(1_000_000);
+        contract.early_withdraw_penalty_bps = 500;
+        contract.stake(String::from("Alice"), 10_000);
+        contract.start_date = Utc::now() - Duration::days(8);
+        assert_eq!(contract.unstake("Alice", 4_000), Ok(4_000));
+        assert_eq!(contract.total_coins, 1_000_000);
+    }
+
+    #[test]
+    fn test_reward_share_bps() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 15_000);
+        assert_eq!(contract.reward_share_bps("Alice"), Some(2_500));
+        assert_eq!(contract.reward_share_bps("Bob"), Some(7_500));
+    }
+
+    #[test]
+    fn test_reward_share_bps_unknown_user() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        assert_eq!(contract.reward_share_bps("Bob"), None);
+    }
+
+    #[test]
+    fn test_distribute_tiered_rewards() {
+        let mut contract = Contract::new(1_000_000);
+        contract.reward_tiers = vec![(5_000, 15_000), (50_000, 20_000)];
+        contract.stake(String::from("Alice"), 1_000);
+        contract.stake(String::from("Bob"), 1_000);
+        contract.stake(String::from("Carol"), 8_000);
+
+        let plain = contract.distribute_rewards();
+        let tiered = contract.distribute_tiered_rewards();
+        assert_eq!(plain[2], ("Carol".to_string(), 800_000));
+        assert_eq!(tiered[2], ("Carol".to_string(), 857_144));
+        assert_eq!(tiered[0], ("Alice".to_string(), 71_428));
+        assert_eq!(
+            tiered.iter().map(|(_, reward)| reward).sum::<u128>(),
+            1_000_000
+        );
+    }
+
+    #[test]
+    fn test_locked_stake_earns_more() {
+        let mut contract = Contract::new(1_000_000);
+        contract
+            .stake_locked(String::from("Alice"), 5_000, Duration::weeks(10))
+            .unwrap();
+        contract.stake(String::from("Bob"), 5_000);
+        assert_eq!(Contract::lock_bonus_bps(Duration::weeks(10)), 1_000);
+
+        let rewards = contract.distribute_lockup_rewards();
+        assert_eq!(rewards[0], ("Alice".to_string(), 523_810));
+        assert_eq!(rewards[1], ("Bob".to_string(), 476_190));
+        assert_eq!(contract.distribute_rewards()[0].1, 500_000);
+    }
+
+    #[test]
+    fn test_unstake_blocked_until_lock_expires() {
+        let clock = MockClock::new(Utc::now());
+        let mut contract =
+            Contract::new_with_clock(1_000_000, Duration::days(7), Box::new(clock.clone()));
+        contract
+            .stake_locked(String::from("Alice"), 5_000, Duration::days(30))
+            .unwrap();
+        assert_eq!(
+            contract.unstake("Alice", 1_000),
+            Err(StakingError::StillLocked)
+        );
+        assert_eq!(contract.stakers.get("Alice").unwrap().amount, 5_000);
+
+        clock.advance(Duration::days(30));
+        assert_eq!(contract.unstake("Alice", 1_000), Ok(1_000));
+    }
+
+    #[test]
+    fn test_snapshot_is_detached() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        let snapshot = contract.snapshot();
+        let copy = snapshot.clone();
+
+        contract.stake(String::from("Carol"), 1_000);
+        contract.unstake("Alice", 5_000).unwrap();
+        contract.total_coins = 0;
+
+        assert_eq!(snapshot, copy);
+        assert_eq!(snapshot.total_coins, 1_000_000);
+        assert_eq!(snapshot.total_staked, 25_000);
+        assert_eq!(snapshot.start_date, contract.start_date);
+        assert_eq!(snapshot.stakers.len(), 2);
+        assert_eq!(snapshot.stakers.get("Alice").unwrap().amount, 5_000);
+    }
+
+    #[test]
+    fn test_distribution_to_csv() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Bob"), 20_000);
+        contract.stake(String::from("Alice"), 5_000);
+        assert_eq!(
+            contract.distribution_to_csv(),
+            "user,reward\nAlice,200000\nBob,800000\n"
+        );
+    }
+
+    #[test]
+    fn test_distribution_to_csv_empty_pool() {
+        let contract = Contract::new(1_000_000);
+        assert_eq!(contract.distribution_to_csv(), "user,reward\n");
+    }
+
+    #[test]
+    fn test_distribution_to_csv_quotes_names() {
+        let mut contract = Contract::new(100);
+        contract.stake(String::from("Smith, \"Al\""), 1);
+        assert_eq!(
+            contract.distribution_to_csv(),
+            "user,reward\n\"Smith, \"\"Al\"\"\",100\n"
+        );
+    }
+
+    #[test]
+    fn test_remove_staker() {
+        let mut contract = Contract::new_with_owner(1_000_000, String::from("admin"));
+        contract
+            .stake_locked(String::from("Alice"), 5_000, Duration::weeks(4))
+            .unwrap();
+        contract.stake(String::from("Bob"), 20_000);
+        assert_eq!(contract.remove_staker("admin", "Alice"), Ok(5_000));
+        assert!(!contract.stakers.contains_key("Alice"));
+        assert_eq!(contract.total_staked(), 20_000);
+    }
+
+    #[test]
+    fn test_remove_staker_unknown() {
+        let mut contract = Contract::new_with_owner(1_000_000, String::from("admin"));
+        assert_eq!(
+            contract.remove_staker("admin", "Alice"),
+            Err(StakingError::UnknownStaker)
+        );
+    }
+
+    #[test]
+    fn test_empty_username_rejected() {
+        let mut contract = Contract::new(1_000_000);
+        assert_eq!(
+            contract.try_stake(String::new(), 5_000),
+            Err(StakingError::InvalidUser)
+        );
+        assert_eq!(
+            contract.try_stake(String::from("  \t"), 5_000),
+            Err(StakingError::InvalidUser)
+        );
+        assert!(contract.stakers.is_empty());
+    }
+
+    #[test]
+    fn test_normalize_usernames_merges_case() {
+        let mut contract = Contract::new(1_000_000);
+        contract.normalize_usernames = true;
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("alice"), 3_000);
+        contract.stake(String::from("ALICE"), 2_000);
+        assert_eq!(contract.staker_count(), 1);
+        assert_eq!(contract.stakers.get("alice").unwrap().amount, 10_000);
+    }
+
+    #[test]
+    fn test_usernames_case_sensitive_by_default() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("alice"), 3_000);
+        assert_eq!(contract.staker_count(), 2);
+    }
+
+    #[test]
+    fn test_contract_builder() {
+        let clock = MockClock::new(Utc::now());
+        let contract = Contract::builder()
+            .total_coins(1_000_000)
+            .staking_duration(Duration::days(3))
+            .min_stake(100)
+            .max_total_stake(50_000)
+            .early_withdraw_penalty_bps(250)
+            .reward_tiers(vec![(10_000, 12_000)])
+            .normalize_usernames(true)
+            .stake_fee_bps(30)
+            .slash_to_pool(true)
+            .clock(Box::new(clock.clone()))
+            .build();
+        assert_eq!(contract.total_coins, 1_000_000);
+        assert_eq!(contract.staking_duration, Duration::days(3));
+        assert_eq!(contract.min_stake, 100);
+        assert_eq!(contract.max_total_stake, Some(50_000));
+        assert_eq!(contract.early_withdraw_penalty_bps, 250);
+        assert_eq!(contract.reward_tiers, vec![(10_000, 12_000)]);
+        assert!(contract.normalize_usernames);
+        assert_eq!(contract.stake_fee_bps, 30);
+        assert!(contract.slash_to_pool);
+        assert_eq!(contract.start_date, clock.now());
+    }
+
+    #[test]
+    fn test_contract_builder_defaults() {
+        let contract = Contract::builder().total_coins(1_000_000).build();
+        let mut expected = Contract::new(1_000_000);
+        expected.start_date = contract.start_date;
+        assert_eq!(contract, expected);
+    }
+
+    #[test]
+    fn test_restake_compounds_rewards() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        contract.accrue_rewards();
+
+        contract.start_date = Utc::now() - Duration::days(8);
+        assert_eq!(contract.restake("Alice"), Ok(200_000));
+        assert_eq!(contract.stakers.get("Alice").unwrap().amount, 205_000);
+        assert_eq!(contract.unclaimed.get("Alice"), None);
+        assert_eq!(contract.restake("Alice"), Ok(0));
+        assert_eq!(contract.stakers.get("Alice").unwrap().amount, 205_000);
+    }
+
+    #[test]
+    fn test_restake_unknown_staker() {
+        let mut contract = Contract::new(1_000_000);
+        assert_eq!(contract.restake("Alice"), Err(StakingError::UnknownStaker));
+    }
+
+    #[test]
+    fn test_epochs() {
+        let mut contract = Contract::new_with_owner(1_000_000, String::from("admin"));
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        contract.advance_epoch("admin", 500_000).unwrap();
+        assert_eq!(contract.epoch, 1);
+        assert_eq!(contract.total_coins, 500_000);
+
+        contract.stake(String::from("Carol"), 25_000);
+        contract.advance_epoch("admin", 0).unwrap();
+        assert_eq!(contract.epoch, 2);
+
+        assert_eq!(
+            contract.distribution_for_epoch(0),
+            Some(&[("Alice".to_string(), 200_000), ("Bob".to_string(), 800_000),][..])
+        );
+        assert_eq!(
+            contract.distribution_for_epoch(1),
+            Some(
+                &[
+                    ("Alice".to_string(), 50_000),
+                    ("Bob".to_string(), 200_000),
+                    ("Carol".to_string(), 250_000),
+                ][..]
+            )
+        );
+        assert_eq!(contract.distribution_for_epoch(2), None);
+        assert_eq!(contract.staker_count(), 3);
+    }
+
+    #[test]
+    fn test_voting_power() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        assert_eq!(contract.voting_power("Alice"), Some(5_000));
+        assert_eq!(contract.voting_power("Bob"), None);
+    }
+
+    #[test]
+    fn test_quorum_reached() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 2_000);
+        contract.stake(String::from("Bob"), 3_000);
+        contract.stake(String::from("Carol"), 5_000);
+        assert!(contract.quorum_reached(&["Alice", "Bob"], 5_000));
+        assert!(contract.quorum_reached(&["Carol", "Dave"], 5_000));
+        assert!(!contract.quorum_reached(&["Alice", "Alice", "Alice"], 5_000));
+        assert!(!contract.quorum_reached(&["Bob"], 5_000));
+    }
+
+    #[test]
+    fn test_quadratic_voting_power() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Whale"), 1_000_000);
+        for minnow in ["Alice", "Bob", "Carol", "Dave"] {
+            contract.stake(minnow.to_string(), 10_000);
+        }
+        let minnows = ["Alice", "Bob", "Carol", "Dave"];
+        let linear: u64 = minnows
+            .iter()
+            .filter_map(|m| contract.voting_power(m))
+            .sum();
+        let quadratic: u64 = minnows
+            .iter()
+            .filter_map(|m| contract.quadratic_voting_power(m))
+            .sum();
+
+        assert_eq!(contract.quadratic_voting_power("Whale"), Some(1_000));
+        assert_eq!(contract.quadratic_voting_power("Alice"), Some(100));
+        // Linear: the whale outweighs the minnows 25:1; quadratic: only 2.5:1.
+        assert_eq!(contract.voting_power("Whale").unwrap() / linear, 25);
+        assert_eq!(quadratic, 400);
+        assert_eq!(contract.quadratic_voting_power("Nobody"), None);
+    }
+
+    #[test]
+    fn test_quadratic_voting_power_large_stake() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), u64::MAX);
+        assert_eq!(
+            contract.quadratic_voting_power("Alice"),
+            Some(4_294_967_295)
+        );
+    }
+
+    #[test]
+    fn test_stake_fee() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake_fee_bps = 100;
+        contract.stake(String::from("Alice"), 10_000);
+        assert_eq!(contract.stakers.get("Alice").unwrap().amount, 9_900);
+        assert_eq!(contract.fee_pool, 100);
+
+        contract.stake(String::from("Bob"), 5_000);
+        contract.stake(String::from("Alice"), 1_000);
+        assert_eq!(contract.fee_pool, 160);
+        assert_eq!(contract.stakers.get("Alice").unwrap().amount, 10_890);
+    }
+
+    #[test]
+    fn test_withdraw_fees() {
+        let mut contract = Contract::new_with_owner(1_000_000, String::from("admin"));
+        contract.stake_fee_bps = 250;
+        contract.stake(String::from("Alice"), 10_000);
+        assert_eq!(contract.withdraw_fees("admin"), Ok(250));
+        assert_eq!(contract.fee_pool, 0);
+        assert_eq!(contract.withdraw_fees("admin"), Ok(0));
+    }
+
+    #[test]
+    fn test_pause_blocks_staking() {
+        let mut contract = Contract::new_with_owner(1_000_000, String::from("admin"));
+        contract.pause("admin").unwrap();
+        assert!(contract.is_paused());
+        assert!(contract.is_staking_open());
+        assert_eq!(
+            contract.try_stake(String::from("Alice"), 5_000),
+            Err(StakingError::Paused)
+        );
+        assert!(contract.stakers.is_empty());
+
+        contract.unpause("admin").unwrap();
+        assert!(!contract.is_paused());
+        assert_eq!(contract.try_stake(String::from("Alice"), 5_000), Ok(()));
+    }
+
+    #[test]
+    fn test_distribute_rewards_map() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        contract.stake(String::from("Carol"), 25_000);
+        let rewards = contract.distribute_rewards_map();
+        assert_eq!(rewards.len(), 3);
+        assert_eq!(rewards["Bob"], 400_000);
+        assert_eq!(rewards.get("Dave"), None);
+    }
+
+    #[test]
+    fn test_try_distribute_rewards_min_stakers() {
+        let mut contract = Contract::builder()
+            .total_coins(1_000_000)
+            .min_stakers_for_distribution(2)
+            .build();
+        contract.stake(String::from("Alice"), 5_000);
+        assert_eq!(
+            contract.try_distribute_rewards(),
+            Err(StakingError::TooFewStakers)
+        );
+
+        contract.stake(String::from("Bob"), 20_000);
+        assert_eq!(
+            contract.try_distribute_rewards(),
+            Ok(contract.distribute_rewards())
+        );
+    }
+
+    #[test]
+    fn test_state_hash_ignores_insertion_order() {
+        let mut a = Contract::new(1_000_000);
+        a.stake(String::from("Alice"), 5_000);
+        a.stake(String::from("Bob"), 20_000);
+        a.stake(String::from("Carol"), 1_000);
+
+        let mut b = Contract::new(1_000_000);
+        b.start_date = a.start_date;
+        b.stake(String::from("Carol"), 1_000);
+        b.stake(String::from("Bob"), 20_000);
+        b.stake(String::from("Alice"), 5_000);
+        assert_eq!(a.state_hash(), b.state_hash());
+
+        b.stake(String::from("Alice"), 1);
+        assert_ne!(a.state_hash(), b.state_hash());
+    }
+
+    #[test]
+    fn test_shared_contract_concurrent_staking() {
+        let shared = SharedContract::new(Contract::new(1_000_000));
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let shared = shared.clone();
+                std::thread::spawn(move || shared.stake(format!("user{}", i), 1_000))
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), Ok(()));
+        }
+        assert_eq!(shared.staker_count(), Ok(8));
+        assert_eq!(shared.total_staked(), Ok(8_000));
+        assert_eq!(shared.unstake("user0", 1_000), Ok(1_000));
+        assert_eq!(shared.distribute_rewards().unwrap().len(), 7);
+    }
+
+    #[test]
+    fn test_shared_contract_poisoned_lock() {
+        let shared = SharedContract::new(Contract::new(1_000_000));
+        let poisoner = shared.clone();
+        let _ = std::thread::spawn(move || {
+            let _guard = poisoner.inner.lock().unwrap();
+            panic!("poison the lock");
+        })
+        .join();
+        assert_eq!(
+            shared.stake(String::from("Alice"), 1_000),
+            Err(StakingError::Locked)
+        );
+    }
+
+    #[test]
+    fn test_vested_amount() {
+        let clock = MockClock::new(Utc::now());
+        let mut contract =
+            Contract::new_with_clock(1_000_000, Duration::days(7), Box::new(clock.clone()));
+        contract.owner = String::from("admin");
+        contract.vesting_duration = Duration::days(30);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        assert_eq!(contract.vested_amount("Alice", clock.now()), 0);
+
+        contract.finalize("admin").unwrap();
+        let distributed_at = clock.now();
+        assert_eq!(contract.vested_amount("Alice", distributed_at), 0);
+        assert_eq!(
+            contract.vested_amount("Alice", distributed_at + Duration::days(15)),
+            100_000
+        );
+        assert_eq!(
+            contract.vested_amount("Alice", distributed_at + Duration::days(30)),
+            200_000
+        );
+        assert_eq!(
+            contract.vested_amount("Bob", distributed_at + Duration::days(90)),
+            800_000
+        );
+        assert_eq!(contract.vested_amount("Carol", distributed_at), 0);
+    }
+
+    #[test]
+    fn test_vesting_cliff() {
+        let clock = MockClock::new(Utc::now());
+        let mut contract =
+            Contract::new_with_clock(1_000_000, Duration::days(7), Box::new(clock.clone()));
+        contract.owner = String::from("admin");
+        contract.vesting_duration = Duration::days(40);
+        contract.vesting_cliff = Duration::days(10);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.finalize("admin").unwrap();
+        let distributed_at = clock.now();
+
+        let just_before_cliff = distributed_at + Duration::days(10) - Duration::seconds(1);
+        assert_eq!(contract.vested_amount("Alice", just_before_cliff), 0);
+        assert_eq!(
+            contract.vested_amount("Alice", distributed_at + Duration::days(10)),
+            250_000
+        );
+        assert_eq!(
+            contract.vested_amount("Alice", distributed_at + Duration::days(20)),
+            500_000
+        );
+        assert_eq!(
+            contract.vested_amount("Alice", distributed_at + Duration::days(40)),
+            1_000_000
+        );
+    }
+
+    #[test]
+    fn test_referral_bonus() {
+        let mut contract = Contract::new(1_000_000);
+        contract.referral_bonus_bps = 1_000;
+        contract.stake(String::from("Alice"), 10_000);
+        contract.stake(String::from("Bob"), 10_000);
+        contract
+            .stake_with_referral(String::from("Carol"), 5_000, Some(String::from("Alice")))
+            .unwrap();
+        contract
+            .stake_with_referral(String::from("Dave"), 5_000, Some(String::from("Alice")))
+            .unwrap();
+        contract
+            .stake_with_referral(String::from("Dave"), 5_000, Some(String::from("Bob")))
+            .unwrap();
+        assert_eq!(contract.referral_count("Alice"), 2);
+        assert_eq!(contract.referral_count("Bob"), 0);
+
+        let rewards = contract.distribute_with_referral_bonus();
+        assert_eq!(rewards[0], ("Alice".to_string(), 324_325));
+        assert_eq!(rewards[1], ("Bob".to_string(), 270_270));
+        assert!(rewards[0].1 > rewards[1].1);
+        assert_eq!(
+            rewards.iter().map(|(_, reward)| reward).sum::<u128>(),
+            1_000_000
+        );
+    }
+
+    #[test]
+    fn test_self_referral_ignored() {
+        let mut contract = Contract::new(1_000_000);
+        contract
+            .stake_with_referral(String::from("Alice"), 5_000, Some(String::from("Alice")))
+            .unwrap();
+        assert_eq!(contract.referral_count("Alice"), 0);
+        assert!(contract.referred_by.is_empty());
+    }
+
+    #[test]
+    fn test_whitelist() {
+        let mut contract = Contract::new(1_000_000);
+        contract.allow(String::from("Alice"));
+        assert_eq!(contract.try_stake(String::from("Alice"), 5_000), Ok(()));
+        assert_eq!(
+            contract.try_stake(String::from("Bob"), 5_000),
+            Err(StakingError::NotWhitelisted)
+        );
+        assert!(!contract.stakers.contains_key("Bob"));
+    }
+
+    #[test]
+    fn test_whitelist_disallow() {
+        let mut contract = Contract::new(1_000_000);
+        contract.allow(String::from("Alice"));
+        contract.stake(String::from("Alice"), 5_000);
+        contract.disallow("Alice");
+        assert_eq!(
+            contract.try_stake(String::from("Alice"), 5_000),
+            Err(StakingError::NotWhitelisted)
+        );
+        assert_eq!(contract.stakers.get("Alice").unwrap().amount, 5_000);
+    }
+
+    #[test]
+    fn test_no_whitelist_is_open() {
+        let mut contract = Contract::new(1_000_000);
+        contract.disallow("Alice");
+        assert_eq!(contract.whitelist, None);
+        assert_eq!(contract.try_stake(String::from("Alice"), 5_000), Ok(()));
+    }
+
+    #[test]
+    fn test_get_stake() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Alice"), 1_000);
+        assert_eq!(contract.get_stake("Alice"), Some(6_000));
+        assert_eq!(contract.get_stake("Bob"), None);
+    }
+
+    #[test]
+    fn test_add_bonus_before_distribution() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        contract.add_bonus(500_000);
+        assert_eq!(contract.total_coins, 1_500_000);
+        assert_eq!(contract.reward_of("Alice"), Some(300_000));
+        assert_eq!(contract.reward_of("Bob"), Some(1_200_000));
+    }
+
+    #[test]
+    fn test_add_bonus_after_finalize() {
+        let mut contract = Contract::new_with_owner(1_000_000, String::from("admin"));
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        contract.finalize("admin").unwrap();
+        contract.add_bonus(100_000);
+        assert_eq!(contract.total_coins, 1_000_000);
+        assert_eq!(contract.unclaimed.get("Alice"), Some(&20_000));
+        assert_eq!(contract.unclaimed.get("Bob"), Some(&80_000));
+    }
+
+    #[test]
+    fn test_stake_saturates_at_u64_max() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), u64::MAX);
+        contract.stake(String::from("Alice"), 1);
+        assert_eq!(contract.get_stake("Alice"), Some(u64::MAX));
+
+        contract.stake(String::from("Bob"), u64::MAX);
+        assert_eq!(contract.total_staked(), u64::MAX);
+        assert_eq!(
+            contract.distribute_rewards()[0],
+            ("Alice".to_string(), 500_000)
+        );
+    }
+
+    #[test]
+    fn test_apr_bps() {
+        let mut contract = Contract::new(10_000);
+        contract.stake(String::from("Alice"), 750_000);
+        contract.stake(String::from("Bob"), 250_000);
+        // Alice earns 7_500 (1%) over 30 days, about 12.17% a year.
+        assert_eq!(contract.apr_bps("Alice", Duration::days(30)), Some(1_216));
+        assert_eq!(contract.apr_bps("Alice", Duration::days(365)), Some(100));
+        assert_eq!(contract.apr_bps("Carol", Duration::days(30)), None);
+        assert_eq!(contract.apr_bps("Alice", Duration::zero()), None);
+    }
+
+    #[test]
+    fn test_event_log_records_stake_unstake_distribute() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let clock = MockClock::new(start);
+        let mut contract =
+            Contract::new_with_clock(1_000, Duration::days(7), Box::new(clock.clone()));
+
+        contract.stake(String::from("Alice"), 500);
+        clock.advance(Duration::hours(1));
+        contract.unstake("Alice", 200).unwrap();
+        contract.distribute_rewards();
+
+        assert_eq!(
+            contract.events(),
+            &[
+                Event::Staked {
+                    user: String::from("Alice"),
+                    amount: 500,
+                    at: start,
+                },
+                Event::Unstaked {
+                    user: String::from("Alice"),
+                    amount: 200,
+                    at: start + Duration::hours(1),
+                },
+                Event::Distributed { total: 1_000 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_on_distribute_callback_fires_per_staker() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+
+        let total = Arc::new(Mutex::new(0u128));
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let (callback_total, callback_calls) = (total.clone(), calls.clone());
+        contract.on_distribute(move |user, reward| {
+            *callback_total.lock().unwrap() += reward;
+            callback_calls
+                .lock()
+                .unwrap()
+                .push((user.to_string(), reward));
+        });
+        contract.distribute_rewards();
+
+        assert_eq!(*total.lock().unwrap(), 1_000_000);
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                (String::from("Alice"), 200_000),
+                (String::from("Bob"), 800_000)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rank_of() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        contract.stake(String::from("Carol"), 10_000);
+        contract.stake(String::from("Dave"), 10_000);
+        contract.stake(String::from("Eve"), 1_000);
+
+        assert_eq!(contract.rank_of("Bob"), Some((1, 5)));
+        assert_eq!(contract.rank_of("Carol"), Some((2, 5)));
+        assert_eq!(contract.rank_of("Dave"), Some((2, 5)));
+        assert_eq!(contract.rank_of("Alice"), Some((4, 5)));
+        assert_eq!(contract.rank_of("Eve"), Some((5, 5)));
+        assert_eq!(contract.rank_of("Mallory"), None);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        let mut other = Contract::new(500_000);
+        other.stake(String::from("Bob"), 10_000);
+        other.stake(String::from("Carol"), 15_000);
+
+        contract.merge(other).unwrap();
+
+        assert_eq!(contract.total_coins, 1_500_000);
+        assert_eq!(contract.get_stake("Alice"), Some(5_000));
+        assert_eq!(contract.get_stake("Bob"), Some(30_000));
+        assert_eq!(contract.get_stake("Carol"), Some(15_000));
+        assert_eq!(contract.total_staked(), 50_000);
+
+        let mut finalized = Contract::new_with_owner(100, String::from("admin"));
+        finalized.finalize("admin").unwrap();
+        assert_eq!(contract.merge(finalized), Err(StakingError::Finalized));
+        assert_eq!(contract.total_coins, 1_500_000);
+    }
+
+    #[test]
+    fn test_stake_cooldown_blocks_rapid_restake() {
+        let clock = MockClock::new(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        let mut contract =
+            Contract::new_with_clock(1_000_000, Duration::days(7), Box::new(clock.clone()));
+        contract.stake_cooldown = Duration::hours(1);
+
+        assert_eq!(contract.try_stake(String::from("Alice"), 5_000), Ok(()));
+        clock.advance(Duration::minutes(30));
+        assert_eq!(
+            contract.try_stake(String::from("Alice"), 5_000),
+            Err(StakingError::CooldownActive)
+        );
+        assert_eq!(contract.try_stake(String::from("Bob"), 5_000), Ok(()));
+        assert_eq!(contract.get_stake("Alice"), Some(5_000));
+    }
+
+    #[test]
+    fn test_stake_after_cooldown_passes() {
+        let clock = MockClock::new(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        let mut contract =
+            Contract::new_with_clock(1_000_000, Duration::days(7), Box::new(clock.clone()));
+        contract.stake_cooldown = Duration::hours(1);
+
+        contract.stake(String::from("Alice"), 5_000);
+        clock.advance(Duration::hours(1));
+        assert_eq!(contract.try_stake(String::from("Alice"), 5_000), Ok(()));
+        assert_eq!(contract.get_stake("Alice"), Some(10_000));
+    }
+
+    #[test]
+    fn test_from_roster_json() {
+        let contract =
+            Contract::from_roster_json(1_000_000, r#"{ "Alice": 5000, "Bob": 20000 }"#).unwrap();
+        assert_eq!(contract.total_coins, 1_000_000);
+        assert_eq!(contract.get_stake("Alice"), Some(5_000));
+        assert_eq!(contract.get_stake("Bob"), Some(20_000));
+        assert!(contract.is_staking_open());
+    }
+
+    #[test]
+    fn test_from_roster_json_rejects_invalid_entries() {
+        assert_eq!(
+            Contract::from_roster_json(1_000_000, r#"{ "Alice": 5000, "Bob": 0 }"#),
+            Err(StakingError::ZeroAmount)
+        );
+        assert_eq!(
+            Contract::from_roster_json(1_000_000, r#"{ " ": 5000 }"#),
+            Err(StakingError::InvalidUser)
+        );
+        assert!(matches!(
+            Contract::from_roster_json(1_000_000, r#"{ "Alice": -5 }"#),
+            Err(StakingError::InvalidRoster(_))
+        ));
+    }
+
+    #[test]
+    fn test_max_reward_per_user_redistributes_excess() {
+        let mut contract = Contract::new(1_000_000);
+        contract.max_reward_per_user = Some(500_000);
+        contract.stake(String::from("Whale"), 80_000);
+        contract.stake(String::from("Alice"), 10_000);
+        contract.stake(String::from("Bob"), 30_000);
+
+        let rewards = contract.distribute_rewards();
+        assert_eq!(
+            rewards,
+            vec![
+                (String::from("Alice"), 125_000),
+                (String::from("Bob"), 375_000),
+                (String::from("Whale"), 500_000)
+            ]
+        );
+        assert_eq!(rewards.iter().map(|(_, r)| r).sum::<u128>(), 1_000_000);
+    }
+
+    #[test]
+    fn test_max_reward_per_user_when_everyone_is_capped() {
+        let mut contract = Contract::new(1_000_000);
+        contract.max_reward_per_user = Some(300_000);
+        contract.stake(String::from("Alice"), 10_000);
+        contract.stake(String::from("Bob"), 30_000);
+
+        assert_eq!(
+            contract.distribute_rewards(),
+            vec![
+                (String::from("Alice"), 300_000),
+                (String::from("Bob"), 300_000)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_distribute_prorated_rewards() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let clock = MockClock::new(start);
+        let mut contract =
+            Contract::new_with_clock(1_000_000, Duration::days(8), Box::new(clock.clone()));
+        contract.stake(String::from("Alice"), 10_000);
+        clock.advance(Duration::days(4));
+        contract.stake(String::from("Bob"), 10_000);
+
+        let rewards = contract.distribute_prorated_rewards(start + Duration::days(8));
+        assert_eq!(
+            rewards,
+            vec![
+                (String::from("Alice"), 666_667),
+                (String::from("Bob"), 333_333)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_distribute_sqrt_weighted_rewards_shrinks_whale_share() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Whale"), 1_000_000);
+        contract.stake(String::from("Alice"), 10_000);
+        contract.stake(String::from("Bob"), 10_000);
+
+        let plain = contract.distribute_rewards();
+        let sqrt = contract.distribute_sqrt_weighted_rewards();
+        assert_eq!(
+            sqrt,
+            vec![
+                (String::from("Alice"), 83_333),
+                (String::from("Bob"), 83_333),
+                (String::from("Whale"), 833_334)
+            ]
+        );
+        assert!(sqrt[2].1 < plain[2].1);
+        assert!(sqrt[0].1 > plain[0].1);
+    }
+
+    #[test]
+    fn test_state_transitions() {
+        let clock = MockClock::new(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        let mut contract =
+            Contract::new_with_clock(1_000_000, Duration::days(7), Box::new(clock.clone()));
+        contract.owner = String::from("admin");
+        assert_eq!(contract.state(), ContractState::Open);
+
+        contract.pause("admin").unwrap();
+        assert_eq!(contract.state(), ContractState::Paused);
+
+        contract.unpause("admin").unwrap();
+        clock.advance(Duration::days(8));
+        assert_eq!(contract.state(), ContractState::Closed);
+
+        contract.finalize("admin").unwrap();
+        assert_eq!(contract.state(), ContractState::Finalized);
+    }
+
+    #[test]
+    fn test_grace_period_allows_existing_staker_top_up() {
+        let clock = MockClock::new(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        let mut contract =
+            Contract::new_with_clock(1_000_000, Duration::days(7), Box::new(clock.clone()));
+        contract.grace_period = Duration::days(1);
+        contract.stake(String::from("Alice"), 5_000);
+
+        clock.advance(Duration::days(7) + Duration::hours(12));
+        assert_eq!(contract.try_stake(String::from("Alice"), 1_000), Ok(()));
+        assert_eq!(contract.get_stake("Alice"), Some(6_000));
+
+        clock.advance(Duration::hours(12));
+        assert_eq!(
+            contract.try_stake(String::from("Alice"), 1_000),
+            Err(StakingError::WindowClosed)
+        );
+    }
+
+    #[test]
+    fn test_grace_period_rejects_new_staker() {
+        let clock = MockClock::new(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        let mut contract =
+            Contract::new_with_clock(1_000_000, Duration::days(7), Box::new(clock.clone()));
+        contract.grace_period = Duration::days(1);
+        contract.stake(String::from("Alice"), 5_000);
+
+        clock.advance(Duration::days(7) + Duration::hours(12));
+        assert_eq!(
+            contract.try_stake(String::from("Bob"), 1_000),
+            Err(StakingError::WindowClosed)
+        );
+        assert_eq!(contract.get_stake("Bob"), None);
+    }
+
+    #[test]
+    fn test_stake_history() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let clock = MockClock::new(start);
+        let mut contract =
+            Contract::new_with_clock(1_000_000, Duration::days(7), Box::new(clock.clone()));
+        contract.stake(String::from("Alice"), 5_000);
+        clock.advance(Duration::days(1));
+        contract.stake(String::from("Alice"), 3_000);
+
+        let history = contract.stake_history("Alice").unwrap();
+        assert_eq!(
+            history,
+            &[(5_000, start), (3_000, start + Duration::days(1))]
+        );
+        assert_eq!(
+            history.iter().map(|(amount, _)| amount).sum::<u64>(),
+            contract.get_stake("Alice").unwrap()
+        );
+        assert_eq!(contract.stake_history("Bob"), None);
+    }
+
+    #[test]
+    fn test_rounding_mode_floor_vs_nearest() {
+        let mut contract = Contract::new(100);
+        contract.stake(String::from("Alice"), 20_000);
+        contract.stake(String::from("Bob"), 20_000);
+        contract.stake(String::from("Carol"), 30_000);
+
+        let floor = contract.distribute_rewards();
+        assert_eq!(
+            floor,
+            vec![
+                (String::from("Alice"), 28),
+                (String::from("Bob"), 28),
+                (String::from("Carol"), 44)
+            ]
+        );
+
+        contract.rounding_mode = RoundingMode::Nearest;
+        let nearest = contract.distribute_rewards();
+        assert_eq!(
+            nearest,
+            vec![
+                (String::from("Alice"), 28),
+                (String::from("Bob"), 29),
+                (String::from("Carol"), 43)
+            ]
+        );
+        assert_eq!(nearest.iter().map(|(_, r)| r).sum::<u128>(), 100);
+    }
+
+    #[test]
+    fn test_rounding_mode_nearest_rounds_up_large_fractions() {
+        let mut contract = Contract::new(1_000_000);
+        contract.rounding_mode = RoundingMode::Nearest;
+        contract.stake(String::from("Alice"), 2_499_996);
+        contract.stake(String::from("Bob"), 7_500_004);
+
+        // Exact shares are 249_999.6 and 750_000.4.
+        assert_eq!(
+            contract.distribute_rewards(),
+            vec![
+                (String::from("Alice"), 250_000),
+                (String::from("Bob"), 750_000)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_distribute_multi() {
+        let mut contract = Contract::new(1_000_000);
+        contract.reward_pools.insert(String::from("GOV"), 1_000);
+        contract.reward_pools.insert(String::from("USDC"), 50_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+
+        let rewards = contract.distribute_multi();
+        assert_eq!(rewards.len(), 2);
+        assert_eq!(
+            rewards["GOV"],
+            vec![(String::from("Alice"), 200), (String::from("Bob"), 800)]
+        );
+        assert_eq!(
+            rewards["USDC"],
+            vec![
+                (String::from("Alice"), 10_000),
+                (String::from("Bob"), 40_000)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_set_stake() {
+        let mut contract = Contract::new_with_owner(1_000_000, String::from("admin"));
+        assert_eq!(
+            contract.set_stake("admin", String::from("Alice"), 5_000),
+            Ok(())
+        );
+        assert_eq!(contract.get_stake("Alice"), Some(5_000));
+
+        contract.stake(String::from("Bob"), 20_000);
+        assert_eq!(
+            contract.set_stake("admin", String::from("Bob"), 7_000),
+            Ok(())
+        );
+        assert_eq!(contract.get_stake("Bob"), Some(7_000));
+
+        assert_eq!(
+            contract.set_stake("admin", String::from("Alice"), 0),
+            Ok(())
+        );
+        assert_eq!(contract.get_stake("Alice"), None);
+        assert_eq!(contract.staker_count(), 1);
+    }
+
+    #[test]
+    fn test_set_stake_respects_state() {
+        let clock = MockClock::new(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        let mut contract =
+            Contract::new_with_clock(1_000_000, Duration::days(7), Box::new(clock.clone()));
+        contract.owner = String::from("admin");
+        contract.pause("admin").unwrap();
+        assert_eq!(
+            contract.set_stake("admin", String::from("Alice"), 5_000),
+            Err(StakingError::Paused)
+        );
+        contract.unpause("admin").unwrap();
+        clock.advance(Duration::days(8));
+        assert_eq!(
+            contract.set_stake("admin", String::from("Alice"), 5_000),
+            Err(StakingError::WindowClosed)
+        );
+    }
+
+    #[test]
+    fn test_concentration_bps() {
+        let mut contract = Contract::new(1_000_000);
+        assert_eq!(contract.concentration_bps(), 0);
+
+        contract.stake(String::from("Whale"), 95_000);
+        contract.stake(String::from("Alice"), 5_000);
+        assert_eq!(contract.concentration_bps(), 9_500);
+
+        let mut balanced = Contract::new(1_000_000);
+        for user in ["Alice", "Bob", "Carol", "Dave"] {
+            balanced.stake(user.to_string(), 10_000);
+        }
+        assert_eq!(balanced.concentration_bps(), 2_500);
+    }
+
+    #[test]
+    fn test_distribute_as_of_snapshot() {
+        let mut contract = Contract::new_with_owner(1_000_000, String::from("admin"));
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        let snapshot = contract.snapshot();
+        let at_snapshot = contract.distribute_rewards();
+
+        contract.stake(String::from("Carol"), 75_000);
+        contract.unstake("Bob", 15_000).unwrap();
+        contract.remove_staker("admin", "Alice").unwrap();
+        contract.add_bonus(500_000);
+
+        assert_eq!(contract.distribute_as_of(&snapshot), at_snapshot);
+        assert_ne!(contract.distribute_rewards(), at_snapshot);
+    }
+
+    #[test]
+    fn test_try_distribute_rewards_empty_pool() {
+        let mut contract = Contract::new(0);
+        contract.stake(String::from("Alice"), 5_000);
+        assert_eq!(
+            contract.try_distribute_rewards(),
+            Err(StakingError::EmptyPool)
+        );
+
+        contract.add_bonus(1_000);
+        assert_eq!(
+            contract.try_distribute_rewards(),
+            Ok(vec![(String::from("Alice"), 1_000)])
+        );
+    }
+
+    #[test]
+    fn test_only_owner_can_pause() {
+        let mut contract = Contract::new_with_owner(1_000_000, String::from("admin"));
+        assert_eq!(contract.pause("Mallory"), Err(StakingError::Unauthorized));
+        assert!(!contract.is_paused());
+        assert_eq!(
+            contract.finalize("Mallory"),
+            Err(StakingError::Unauthorized)
+        );
+        assert_eq!(
+            contract.withdraw_fees("Mallory"),
+            Err(StakingError::Unauthorized)
+        );
+
+        assert_eq!(contract.pause("admin"), Ok(()));
+        assert!(contract.is_paused());
+    }
+
+    #[test]
+    fn test_transfer_ownership() {
+        let mut contract = Contract::builder()
+            .total_coins(1_000_000)
+            .owner(String::from("admin"))
+            .build();
+        assert_eq!(contract.owner(), "admin");
+        assert_eq!(
+            contract.transfer_ownership("Mallory", String::from("Mallory")),
+            Err(StakingError::Unauthorized)
+        );
+
+        assert_eq!(
+            contract.transfer_ownership("admin", String::from("Bob")),
+            Ok(())
+        );
+        assert_eq!(contract.owner(), "Bob");
+        assert_eq!(contract.pause("admin"), Err(StakingError::Unauthorized));
+        assert_eq!(contract.pause("Bob"), Ok(()));
+        assert_eq!(
+            contract.transfer_ownership("Bob", String::from(" ")),
+            Err(StakingError::InvalidUser)
+        );
+        assert_eq!(contract.owner(), "Bob");
+    }
+
+    #[test]
+    fn test_preview_reward_for() {
+        let mut contract = Contract::new(1_000_000);
+        assert_eq!(contract.preview_reward_for(0), 0);
+        assert_eq!(contract.preview_reward_for(5_000), 1_000_000);
+
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        assert_eq!(contract.preview_reward_for(25_000), 500_000);
+        assert_eq!(contract.staker_count(), 2);
+    }
+
+    #[test]
+    fn test_sweep_dust() {
+        let mut contract = Contract::new_with_owner(1_000_000, String::from("admin"));
+        contract.stake(String::from("Alice"), 5);
+        contract.stake(String::from("Bob"), 20_000);
+        contract.stake(String::from("Carol"), 99);
+        contract.stake(String::from("Dave"), 100);
+
+        assert_eq!(
+            contract.sweep_dust("admin", 100),
+            Ok(vec![
+                (String::from("Alice"), 5),
+                (String::from("Carol"), 99)
+            ])
+        );
+        assert_eq!(contract.staker_count(), 2);
+        assert_eq!(contract.get_stake("Bob"), Some(20_000));
+        assert_eq!(contract.get_stake("Dave"), Some(100));
+        assert_eq!(contract.total_coins, 1_000_000);
+    }
+
+    #[test]
+    fn test_sweep_dust_to_pool() {
+        let mut contract = Contract::new_with_owner(1_000_000, String::from("admin"));
+        contract.sweep_to_pool = true;
+        contract.stake(String::from("Alice"), 5);
+        contract.stake(String::from("Bob"), 20_000);
+
+        assert_eq!(
+            contract.sweep_dust("admin", 100),
+            Ok(vec![(String::from("Alice"), 5)])
+        );
+        assert_eq!(contract.total_coins, 1_000_005);
+    }
+
+    #[test]
+    fn test_median_and_mean_stake_odd_count() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        contract.stake(String::from("Carol"), 1_000);
+
+        assert_eq!(contract.median_stake(), Some(5_000));
+        assert_eq!(contract.mean_stake(), Some(8_666));
+    }
+
+    #[test]
+    fn test_median_and_mean_stake_even_count() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        contract.stake(String::from("Carol"), 1_000);
+        contract.stake(String::from("Dave"), 10_000);
+
+        assert_eq!(contract.median_stake(), Some(7_500));
+        assert_eq!(contract.mean_stake(), Some(9_000));
+    }
+
+    #[test]
+    fn test_median_and_mean_stake_empty() {
+        let contract = Contract::new(1_000_000);
+        assert_eq!(contract.median_stake(), None);
+        assert_eq!(contract.mean_stake(), None);
+    }
+
+    #[test]
+    fn test_reward_schedule() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Bob"), 20_000);
+        contract.stake(String::from("Alice"), 5_000);
+
+        assert_eq!(
+            contract.reward_schedule(),
+            vec![
+                RewardRow {
+                    user: String::from("Alice"),
+                    stake: 5_000,
+                    share_bps: 2_000,
+                    reward: 200_000,
+                },
+                RewardRow {
+                    user: String::from("Bob"),
+                    stake: 20_000,
+                    share_bps: 8_000,
+                    reward: 800_000,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extend_window_reopens_staking() {
+        let clock = MockClock::new(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        let mut contract =
+            Contract::new_with_clock(1_000_000, Duration::days(7), Box::new(clock.clone()));
+        contract.owner = String::from("admin");
+        clock.advance(Duration::days(8));
+        assert!(!contract.is_staking_open());
+
+        assert_eq!(contract.extend_window("admin", Duration::days(3)), Ok(()));
+        assert!(contract.is_staking_open());
+        assert_eq!(contract.remaining_window(), Duration::days(2));
+        assert_eq!(contract.try_stake(String::from("Alice"), 5_000), Ok(()));
+
+        assert_eq!(
+            contract.extend_window("admin", -Duration::days(30)),
+            Err(StakingError::InvalidDuration)
+        );
+        assert_eq!(
+            contract.extend_window("admin", Duration::zero()),
+            Err(StakingError::InvalidDuration)
+        );
+        assert!(contract.is_staking_open());
+
+        contract.finalize("admin").unwrap();
+        assert_eq!(
+            contract.extend_window("admin", Duration::days(1)),
+            Err(StakingError::Finalized)
+        );
+    }
+
+    #[test]
+    fn test_tie_break_by_name_gives_dust_to_first_name() {
+        let mut contract = Contract::new(100);
+        contract.stake(String::from("Bob"), 1_000);
+        contract.stake(String::from("Alice"), 1_000);
+        contract.stake(String::from("Carol"), 1_000);
+
+        assert_eq!(
+            contract.distribute_rewards(),
+            vec![
+                (String::from("Alice"), 34),
+                (String::from("Bob"), 33),
+                (String::from("Carol"), 33)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tie_break_seeded_is_reproducible() {
+        let mut contract = Contract::new(100);
+        contract.tie_break = TieBreak::Seeded(42);
+        contract.stake(String::from("Alice"), 1_000);
+        contract.stake(String::from("Bob"), 1_000);
+        contract.stake(String::from("Carol"), 1_000);
+
+        let rewards = contract.distribute_rewards();
+        assert_eq!(rewards, contract.clone().distribute_rewards());
+        assert_eq!(rewards.iter().map(|(_, r)| r).sum::<u128>(), 100);
+        let winner = &contract.ranked_stakers()[0].0;
+        assert_eq!(
+            rewards.iter().find(|(user, _)| user == winner).unwrap().1,
+            34
+        );
+    }
+
+    #[test]
+    fn test_effective_weights() {
+        let mut contract = Contract::new(1_000_000);
+        contract.reward_tiers = vec![(10_000, 15_000)];
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+
+        assert_eq!(
+            contract.effective_weights(),
+            vec![
+                (String::from("Alice"), 5_000),
+                (String::from("Bob"), 20_000)
+            ]
+        );
+
+        contract.distribution_mode = DistributionMode::Tiered;
+        assert_eq!(
+            contract.effective_weights(),
+            vec![
+                (String::from("Alice"), 50_000_000),
+                (String::from("Bob"), 300_000_000)
+            ]
+        );
+        assert_eq!(
+            contract.distribute_rewards(),
+            contract.distribute_tiered_rewards()
+        );
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let clock = MockClock::new(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        let mut contract =
+            Contract::new_with_clock(1_000_000, Duration::days(7), Box::new(clock.clone()));
+        for i in 0..100 {
+            contract.stake(format!("user{:03}", i), 1_000 + i);
+            clock.advance(Duration::minutes(1));
+        }
+        contract
+            .stake_locked(String::from("user000"), 500, Duration::weeks(2))
+            .unwrap();
+
+        let bytes = contract.to_bytes();
+        let restored = Contract::from_bytes(&bytes).unwrap();
+        assert_eq!(restored, contract);
+        assert!(bytes.len() < contract.to_json().len());
+
+        assert!(matches!(
+            Contract::from_bytes(&bytes[..10]),
+            Err(StakingError::InvalidBytes(_))
+        ));
+    }
+
+    #[test]
+    fn test_auto_finalize_caches_distribution_after_window() {
+        let clock = MockClock::new(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        let mut contract =
+            Contract::new_with_clock(1_000_000, Duration::days(7), Box::new(clock.clone()));
+        contract.owner = String::from("admin");
+        contract.auto_finalize = true;
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        contract.distribute_rewards();
+        assert!(!contract.is_finalized());
+
+        clock.advance(Duration::days(8));
+        let first = contract.distribute_rewards();
+        assert!(contract.is_finalized());
+
+        contract.remove_staker("admin", "Alice").unwrap();
+        contract.stakers.get_mut("Bob").unwrap().amount = 1;
+        assert_eq!(contract.distribute_rewards(), first);
+        assert_eq!(
+            first,
+            vec![
+                (String::from("Alice"), 200_000),
+                (String::from("Bob"), 800_000)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_distribute_fraction_drains_pool_gradually() {
+        let mut contract = Contract::new_with_owner(1_000_000, String::from("admin"));
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+
+        assert_eq!(
+            contract.distribute_fraction("admin", 5_000),
+            Ok(vec![
+                (String::from("Alice"), 100_000),
+                (String::from("Bob"), 400_000)
+            ])
+        );
+        assert_eq!(contract.total_coins, 500_000);
+
+        assert_eq!(
+            contract.distribute_fraction("admin", 5_000),
+            Ok(vec![
+                (String::from("Alice"), 50_000),
+                (String::from("Bob"), 200_000)
+            ])
+        );
+        assert_eq!(contract.total_coins, 250_000);
+
+        contract.distribute_fraction("admin", 10_000).unwrap();
+        assert_eq!(contract.total_coins, 0);
+    }
+
+    #[test]
+    fn test_min_reward_per_user_lifts_tiny_staker() {
+        let mut contract = Contract::new(1_000_000);
+        contract.min_reward_per_user = Some(50_000);
+        contract.stake(String::from("Alice"), 100);
+        contract.stake(String::from("Bob"), 20_000);
+        contract.stake(String::from("Carol"), 79_900);
+
+        let rewards = contract.try_distribute_rewards().unwrap();
+        assert_eq!(
+            rewards,
+            vec![
+                (String::from("Alice"), 50_000),
+                (String::from("Bob"), 190_190),
+                (String::from("Carol"), 759_810)
+            ]
+        );
+        assert_eq!(rewards.iter().map(|(_, r)| r).sum::<u128>(), 1_000_000);
+    }
+
+    #[test]
+    fn test_min_reward_per_user_insufficient_pool() {
+        let mut contract = Contract::new(100);
+        contract.min_reward_per_user = Some(50);
+        contract.stake(String::from("Alice"), 100);
+        contract.stake(String::from("Bob"), 20_000);
+        contract.stake(String::from("Carol"), 79_900);
+
+        assert_eq!(
+            contract.try_distribute_rewards(),
+            Err(StakingError::InsufficientPool)
+        );
+    }
+
+    #[test]
+    fn test_stakers_in_range() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let clock = MockClock::new(start);
+        let mut contract =
+            Contract::new_with_clock(1_000_000, Duration::days(7), Box::new(clock.clone()));
+        contract.stake(String::from("Dave"), 1_000);
+        clock.advance(Duration::days(1));
+        contract.stake(String::from("Carol"), 2_000);
+        clock.advance(Duration::days(1));
+        contract.stake(String::from("Bob"), 3_000);
+        clock.advance(Duration::days(1));
+        contract.stake(String::from("Alice"), 4_000);
+
+        assert_eq!(
+            contract.stakers_in_range(start + Duration::days(1), start + Duration::days(2)),
+            vec![(String::from("Carol"), 2_000), (String::from("Bob"), 3_000)]
+        );
+        assert_eq!(
+            contract.stakers_in_range(start + Duration::days(5), start + Duration::days(6)),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_emergency_withdraw_while_paused() {
+        let mut contract = Contract::new_with_owner(1_000_000, String::from("admin"));
+        contract.early_withdraw_penalty_bps = 1_000;
+        contract
+            .stake_locked(String::from("Alice"), 5_000, Duration::weeks(4))
+            .unwrap();
+        contract.pause("admin").unwrap();
+
+        assert_eq!(contract.emergency_withdraw("Alice"), Ok(5_000));
+        assert_eq!(contract.get_stake("Alice"), None);
+        assert_eq!(contract.total_coins, 1_000_000);
+        assert_eq!(
+            contract.emergency_withdraw("Alice"),
+            Err(StakingError::UnknownStaker)
+        );
+    }
+
+    #[test]
+    fn test_emergency_withdraw_requires_pause() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+
+        assert_eq!(
+            contract.emergency_withdraw("Alice"),
+            Err(StakingError::NotPaused)
+        );
+        assert_eq!(contract.get_stake("Alice"), Some(5_000));
+    }
+
+    #[test]
+    fn test_pool_policy_fixed_raises_remaining_rewards() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        let before = contract.reward_of("Alice").unwrap();
+
+        contract.unstake("Bob", 15_000).unwrap();
+        assert_eq!(contract.total_coins, 1_000_000);
+        assert!(contract.reward_of("Alice").unwrap() > before);
+        assert_eq!(contract.reward_of("Alice"), Some(500_000));
+    }
+
+    #[test]
+    fn test_pool_policy_burn_shrinks_pool() {
+        let mut contract = Contract::new(1_000_000);
+        contract.pool_policy = PoolPolicy::Burn;
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+
+        contract.unstake("Bob", 15_000).unwrap();
+        assert_eq!(contract.total_coins, 400_000);
+        assert_eq!(contract.reward_of("Alice"), Some(200_000));
+    }
+
+    #[test]
+    fn test_padded_username_merges_with_trimmed_form() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from(" Alice "), 5_000);
+        contract.stake(String::from("Alice"), 3_000);
+        contract.stake(String::from("Alice\t"), 2_000);
+
+        assert_eq!(contract.staker_count(), 1);
+        assert_eq!(contract.get_stake("Alice"), Some(10_000));
+    }
+
+    #[test]
+    fn test_whitespace_only_username_rejected() {
+        let mut contract = Contract::new(1_000_000);
+        assert_eq!(
+            contract.try_stake(String::from(" \t\n "), 5_000),
+            Err(StakingError::InvalidUser)
+        );
+        assert!(contract.stakers.is_empty());
+    }
+
+    #[test]
+    fn test_distribution_diff() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        let a = contract.snapshot();
+
+        contract.stake(String::from("Alice"), 15_000);
+        contract.stake(String::from("Carol"), 10_000);
+        let b = contract.snapshot();
+
+        assert_eq!(
+            Contract::distribution_diff(&a, &b),
+            vec![
+                (String::from("Alice"), 200_000),
+                (String::from("Bob"), -400_000),
+                (String::from("Carol"), 200_000)
+            ]
+        );
+        assert_eq!(
+            Contract::distribution_diff(&b, &a),
+            vec![
+                (String::from("Alice"), -200_000),
+                (String::from("Bob"), 400_000),
+                (String::from("Carol"), -200_000)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_total_coins_beyond_u64() {
+        let pool = u64::MAX as u128 * 1_000;
+        let mut contract = Contract::new(pool);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+
+        let rewards = contract.distribute_rewards();
+        assert_eq!(
+            rewards,
+            vec![
+                (String::from("Alice"), pool / 5),
+                (String::from("Bob"), pool - pool / 5)
+            ]
+        );
+        assert_eq!(contract.reward_of("Alice"), Some(pool / 5));
+    }
+
+    #[test]
+    fn test_from_json_accepts_unversioned_state() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        let json = contract
+            .to_json()
+            .replace(&format!("\"format_version\":{},", FORMAT_VERSION), "");
+        assert!(!json.contains("format_version"));
+
+        assert_eq!(Contract::from_json(&json).unwrap(), contract);
+    }
+
+    #[test]
+    fn test_distribution_balance() {
+        let mut contract = Contract::new(100);
+        contract.stake(String::from("Alice"), 1_000);
+        contract.stake(String::from("Bob"), 1_000);
+        contract.stake(String::from("Carol"), 1_000);
+        assert_eq!(contract.distribution_balance(), 0);
+
+        contract.max_reward_per_user = Some(30);
+        assert_eq!(contract.distribution_balance(), 10);
+
+        contract.max_reward_per_user = None;
+        contract.min_reward_per_user = Some(40);
+        assert_eq!(contract.distribution_balance(), -20);
+    }
+
+    #[test]
+    fn test_delegate_stake() {
+        let mut contract = Contract::new(1_000_000);
+        contract
+            .delegate_stake(String::from("Custodian"), String::from("Alice"), 5_000)
+            .unwrap();
+        contract.stake(String::from("Bob"), 20_000);
+
+        assert_eq!(contract.delegator_of("Alice"), Some("Custodian"));
+        assert_eq!(contract.delegator_of("Bob"), None);
+        assert_eq!(contract.get_stake("Custodian"), None);
+        assert_eq!(contract.reward_of("Alice"), Some(200_000));
+        assert_eq!(contract.reward_of("Custodian"), None);
+    }
+
+    #[test]
+    fn test_delegate_stake_rejections() {
+        let mut contract = Contract::new(1_000_000);
+        assert_eq!(
+            contract.delegate_stake(String::from(" "), String::from("Alice"), 5_000),
+            Err(StakingError::InvalidUser)
+        );
+        assert_eq!(
+            contract.delegate_stake(String::from("Custodian"), String::from("Alice"), 0),
+            Err(StakingError::ZeroAmount)
+        );
+        assert_eq!(contract.delegator_of("Alice"), None);
+    }
+
+    #[test]
+    fn test_distribute_result_totals() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut contract = Contract::new_with_clock(
+            1_000_000,
+            Duration::days(7),
+            Box::new(MockClock::new(start)),
+        );
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+
+        let result = contract.distribute();
+        assert_eq!(result.rewards, contract.distribute_rewards());
+        assert_eq!(result.total_distributed, 1_000_000);
+        assert_eq!(result.leftover, 0);
+        assert_eq!(result.at, start);
+
+        contract.max_reward_per_user = Some(300_000);
+        let result = contract.distribute();
+        assert_eq!(result.total_distributed, 600_000);
+        assert_eq!(
+            result.total_distributed + result.leftover,
+            contract.total_coins
+        );
+    }
+
+    #[test]
+    fn test_custom_distribution_strategy() {
+        struct Equal;
+
+        impl DistributionStrategy for Equal {
+            fn weights(&self, contract: &Contract) -> Vec<(String, u128)> {
+                contract
+                    .stakers
+                    .keys()
+                    .map(|user| (user.clone(), 1))
+                    .collect()
+            }
+        }
+
+        let mut contract = Contract::new(900_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        contract.stake(String::from("Whale"), 1_000_000);
+        contract.set_distribution_strategy(Box::new(Equal));
+
+        assert_eq!(
+            contract.distribute_rewards(),
+            vec![
+                (String::from("Alice"), 300_000),
+                (String::from("Bob"), 300_000),
+                (String::from("Whale"), 300_000)
+            ]
+        );
+
+        contract.clear_distribution_strategy();
+        assert_eq!(
+            contract.effective_weights()[2],
+            (String::from("Whale"), 1_000_000)
+        );
+    }
+
+    #[test]
+    fn test_frozen_staker_cannot_unstake_but_still_earns() {
+        let mut contract = Contract::new_with_owner(25_000, String::from("admin"));
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+        contract.freeze("admin", String::from("Alice")).unwrap();
+
+        assert_eq!(contract.unstake("Alice", 1_000), Err(StakingError::Frozen));
+        assert_eq!(
+            contract.transfer_stake("Alice", String::from("Carol"), 1_000),
+            Err(StakingError::Frozen)
+        );
+        contract.accrue_rewards();
+        assert_eq!(contract.claim("Alice"), Err(StakingError::Frozen));
+        assert_eq!(
+            contract.distribute_rewards(),
+            vec![
+                (String::from("Alice"), 5_000),
+                (String::from("Bob"), 20_000)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unfreeze_restores_access() {
+        let mut contract = Contract::new_with_owner(25_000, String::from("admin"));
+        contract.stake(String::from("Alice"), 5_000);
+        contract.freeze("admin", String::from("Alice")).unwrap();
+        contract.accrue_rewards();
+        contract.unfreeze("admin", "Alice").unwrap();
+
+        assert!(!contract.is_frozen("Alice"));
+        assert_eq!(contract.claim("Alice"), Ok(25_000));
+        assert_eq!(contract.unstake("Alice", 1_000), Ok(1_000));
+    }
+
+    #[test]
+    fn test_distribute_rewards_includes_zero_reward_stakers() {
+        let mut contract = Contract::new(100);
+        contract.stake(String::from("Minnow"), 1);
+        contract.stake(String::from("Whale"), 1_000_000_000);
+
+        assert_eq!(
+            contract.distribute_rewards(),
+            vec![(String::from("Minnow"), 0), (String::from("Whale"), 100)]
+        );
+    }
+
+    #[test]
+    fn test_distribute_rewards_reports_everyone_when_no_weight_accrued() {
+        let clock = MockClock::new(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        let mut contract =
+            Contract::new_with_clock(1_000, Duration::days(7), Box::new(clock.clone()));
+        contract.distribution_mode = DistributionMode::TimeWeighted;
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+
+        assert_eq!(
+            contract.distribute_rewards(),
+            vec![(String::from("Alice"), 0), (String::from("Bob"), 0)]
+        );
+    }
+
+    #[test]
+    fn test_precision_scale_rounds_minnow_up() {
+        let mut contract = Contract::new(10);
+        contract.stake(String::from("Minnow"), 99);
+        contract.stake(String::from("Whale"), 1_000);
+
+        assert_eq!(
+            contract.distribute_rewards(),
+            vec![(String::from("Minnow"), 0), (String::from("Whale"), 10)]
+        );
+
+        contract.precision_scale = 1_000_000;
+        assert_eq!(
+            contract.distribute_rewards(),
+            vec![(String::from("Minnow"), 1), (String::from("Whale"), 9)]
+        );
+    }
+
+    #[test]
+    fn test_lifetime_distributed_sums_epochs() {
+        let mut contract = Contract::new_with_owner(1_000, String::from("admin"));
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 20_000);
+
+        contract.advance_epoch("admin", 3_000).unwrap();
+        assert_eq!(contract.lifetime_distributed(), 1_000);
+
+        contract.distribute();
+        assert_eq!(contract.lifetime_distributed(), 1_000);
+        contract.distribute_rewards();
+        assert_eq!(contract.lifetime_distributed(), 4_000);
+    }
+
+    #[test]
+    fn test_deadline_moves_with_extend_window() {
+        let mut contract = Contract::new_with_duration(1_000, Duration::days(7));
+        contract.owner = String::from("admin");
+        assert_eq!(contract.deadline(), contract.start_date + Duration::days(7));
+
+        contract.extend_window("admin", Duration::days(3)).unwrap();
+        assert_eq!(
+            contract.deadline(),
+            contract.start_date + Duration::days(10)
+        );
+    }
+
+    #[test]
+    fn test_unowned_contract_refuses_privileged_calls() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+
+        assert_eq!(contract.pause(""), Err(StakingError::Unauthorized));
+        assert_eq!(
+            contract.slash("", "Alice", 1),
+            Err(StakingError::Unauthorized)
+        );
+        assert_eq!(
+            contract.set_stake("Mallory", String::from("Alice"), 0),
+            Err(StakingError::Unauthorized)
+        );
+        assert_eq!(
+            contract.remove_staker("Mallory", "Alice"),
+            Err(StakingError::Unauthorized)
+        );
+        assert_eq!(
+            contract.transfer_ownership("Mallory", String::from("Mallory")),
+            Err(StakingError::Unauthorized)
+        );
+        assert_eq!(contract.owner(), "");
+        assert_eq!(contract.get_stake("Alice"), Some(5_000));
+    }
+
+    #[test]
+    fn test_only_owner_can_freeze() {
+        let mut contract = Contract::new_with_owner(25_000, String::from("admin"));
+        contract.stake(String::from("Alice"), 5_000);
+
+        assert_eq!(
+            contract.freeze("Mallory", String::from("Alice")),
+            Err(StakingError::Unauthorized)
+        );
+        assert!(!contract.is_frozen("Alice"));
+
+        contract.freeze("admin", String::from("Alice")).unwrap();
+        assert_eq!(
+            contract.unfreeze("Alice", "Alice"),
+            Err(StakingError::Unauthorized)
+        );
+        assert!(contract.is_frozen("Alice"));
+    }
+
+    #[test]
+    fn test_frozen_staker_cannot_emergency_withdraw() {
+        let mut contract = Contract::new_with_owner(1_000_000, String::from("admin"));
+        contract.stake(String::from("Alice"), 10);
+        contract.freeze("admin", String::from("Alice")).unwrap();
+        contract.pause("admin").unwrap();
+
+        assert_eq!(
+            contract.emergency_withdraw("Alice"),
+            Err(StakingError::Frozen)
+        );
+        assert_eq!(contract.get_stake("Alice"), Some(10));
+    }
+
+    #[test]
+    fn test_early_withdraw_penalty_capped_at_whole_amount() {
+        let mut contract = Contract::new(1_000_000);
+        contract.early_withdraw_penalty_bps = u16::MAX;
+        contract.stake(String::from("Alice"), 5_000);
+
+        assert_eq!(contract.unstake("Alice", 1_000), Ok(0));
+        assert_eq!(contract.get_stake("Alice"), Some(4_000));
+        assert_eq!(contract.total_coins, 1_001_000);
+        assert_eq!(
+            Contract::builder()
+                .early_withdraw_penalty_bps(20_000)
+                .build()
+                .early_withdraw_penalty_bps,
+            10_000
+        );
+    }
+
+    #[test]
+    fn test_stake_fee_capped_at_whole_deposit() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake_fee_bps = u16::MAX;
+        contract.stake(String::from("Alice"), 5_000);
+
+        assert_eq!(contract.fee_pool, 5_000);
+        assert_eq!(contract.get_stake("Alice"), None);
+        assert_eq!(
+            Contract::builder()
+                .stake_fee_bps(20_000)
+                .build()
+                .stake_fee_bps,
+            10_000
+        );
+    }
+
+    #[test]
+    fn test_transfer_stake_validates_recipient() {
+        let mut contract = Contract::new(1_000_000);
+        contract.normalize_usernames = true;
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("carol"), 1_000);
+
+        assert_eq!(
+            contract.transfer_stake("alice", String::from(" "), 1_000),
+            Err(StakingError::InvalidUser)
+        );
+        assert_eq!(
+            contract.transfer_stake("alice", String::from(" Carol "), 1_000),
+            Ok(())
+        );
+        assert_eq!(contract.get_stake("carol"), Some(2_000));
+        assert_eq!(contract.staker_count(), 2);
+
+        contract.allow(String::from("carol"));
+        assert_eq!(
+            contract.transfer_stake("alice", String::from("Mallory"), 1_000),
+            Err(StakingError::NotWhitelisted)
+        );
+        assert_eq!(contract.get_stake("alice"), Some(4_000));
+    }
+
+    #[test]
+    fn test_normalized_usernames_round_trip() {
+        let mut contract = Contract::new_with_owner(1_000_000, String::from("admin"));
+        contract.normalize_usernames = true;
+        contract
+            .delegate_stake(String::from("Dana"), String::from(" Alice "), 5_000)
+            .unwrap();
+
+        assert_eq!(contract.get_stake("ALICE"), Some(5_000));
+        assert_eq!(contract.delegator_of(" alice"), Some("dana"));
+        assert_eq!(contract.stake_history("Alice").map(<[_]>::len), Some(1));
+        contract.accrue_rewards();
+        assert_eq!(contract.claim("aLiCe "), Ok(1_000_000));
+
+        contract.freeze("admin", String::from(" ALICE ")).unwrap();
+        assert!(contract.is_frozen("Alice"));
+        assert_eq!(contract.unstake("Alice", 1_000), Err(StakingError::Frozen));
+        contract.unfreeze("admin", "alice").unwrap();
+        assert_eq!(contract.unstake(" Alice", 1_000), Ok(1_000));
+        assert_eq!(contract.get_stake("alice"), Some(4_000));
+    }
+
+    #[test]
+    fn test_rounding_mode_nearest_hands_leftover_to_largest_remainders() {
+        let mut contract = Contract::new(7);
+        contract.rounding_mode = RoundingMode::Nearest;
+        contract.stake(String::from("Alice"), 32);
+        for user in ["Bob", "Carol", "Dave", "Erin"] {
+            contract.stake(user.to_string(), 27);
+        }
+
+        // Exact shares are 1.6 for Alice and 1.35 for everyone else.
+        assert_eq!(
+            contract.distribute_rewards(),
+            vec![
+                (String::from("Alice"), 2),
+                (String::from("Bob"), 2),
+                (String::from("Carol"), 1),
+                (String::from("Dave"), 1),
+                (String::from("Erin"), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_precision_scale_sets_fraction_resolution() {
+        let mut contract = Contract::new(2);
+        contract.stake(String::from("Alice"), 262);
+        contract.stake(String::from("Bob"), 69);
+        contract.stake(String::from("Carol"), 69);
+
+        // Exact shares are 1.31, 0.345 and 0.345: equal in tenths, so the
+        // heaviest staker keeps the dust, but Bob's is larger in hundredths.
+        contract.precision_scale = 10;
+        assert_eq!(
+            contract.distribute_rewards(),
+            vec![
+                (String::from("Alice"), 2),
+                (String::from("Bob"), 0),
+                (String::from("Carol"), 0)
+            ]
+        );
+
+        contract.precision_scale = 100;
+        assert_eq!(
+            contract.distribute_rewards(),
+            vec![
+                (String::from("Alice"), 1),
+                (String::from("Bob"), 1),
+                (String::from("Carol"), 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_json_rejects_out_of_range_duration() {
+        let mut json: serde_json::Value =
+            serde_json::from_str(&Contract::new(100).to_json()).unwrap();
+        json["staking_duration"] = serde_json::Value::from(i64::MIN);
+
+        assert!(Contract::from_json(&json.to_string()).is_err());
+    }
+
+    #[test]
+    fn test_advance_epoch_after_finalize_rejected() {
+        let mut contract = Contract::new_with_owner(100, String::from("admin"));
+        contract.stake(String::from("Alice"), 5_000);
+        contract.finalize("admin").unwrap();
+
+        assert_eq!(
+            contract.advance_epoch("admin", 500),
+            Err(StakingError::Finalized)
+        );
+        assert_eq!(contract.epoch, 0);
+        assert_eq!(contract.distribution_for_epoch(0), None);
+        assert_eq!(contract.total_coins, 100);
+    }
+
+    #[test]
+    fn test_distribute_fraction_after_finalize_rejected() {
+        let mut contract = Contract::new_with_owner(700, String::from("admin"));
+        contract.stake(String::from("Alice"), 5_000);
+        contract.finalize("admin").unwrap();
+
+        assert_eq!(
+            contract.distribute_fraction("admin", 10_000),
+            Err(StakingError::Finalized)
+        );
+        assert_eq!(contract.total_coins, 700);
+        assert_eq!(
+            contract.distribute_rewards(),
+            vec![(String::from("Alice"), 700)]
+        );
+    }
+
+    #[test]
+    fn test_strategy_omitting_a_staker_keeps_username_order() {
+        struct SkipAlice;
+
+        impl DistributionStrategy for SkipAlice {
+            fn weights(&self, contract: &Contract) -> Vec<(String, u128)> {
+                contract
+                    .stakers
+                    .keys()
+                    .filter(|user| *user != "Alice")
+                    .map(|user| (user.clone(), 1))
+                    .collect()
+            }
+        }
+
+        let mut contract = Contract::new(100);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 5_000);
+        contract.stake(String::from("Carol"), 5_000);
+        contract.set_distribution_strategy(Box::new(SkipAlice));
+
+        assert_eq!(
+            contract.distribute_rewards(),
+            vec![
+                (String::from("Alice"), 0),
+                (String::from("Bob"), 50),
+                (String::from("Carol"), 50)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_set_stake_records_history_and_events() {
+        let mut contract = Contract::new_with_owner(1_000_000, String::from("admin"));
+        contract.stake(String::from("Alice"), 10);
+        contract.stake(String::from("Alice"), 20);
+
+        contract
+            .set_stake("admin", String::from("Alice"), 50)
+            .unwrap();
+        let history = contract.stake_history("Alice").unwrap();
+        assert_eq!(history.iter().map(|(amount, _)| amount).sum::<u64>(), 50);
+        assert!(matches!(
+            contract.events().last(),
+            Some(Event::Staked { amount: 20, .. })
+        ));
+
+        contract
+            .set_stake("admin", String::from("Alice"), 15)
+            .unwrap();
+        let amounts: Vec<u64> = contract
+            .stake_history("Alice")
+            .unwrap()
+            .iter()
+            .map(|(amount, _)| *amount)
+            .collect();
+        assert_eq!(amounts, vec![10, 5]);
+        assert!(matches!(
+            contract.events().last(),
+            Some(Event::Unstaked { amount: 35, .. })
+        ));
+
+        contract
+            .set_stake("admin", String::from("Alice"), 0)
+            .unwrap();
+        assert_eq!(contract.stake_history("Alice"), None);
+    }
+
+    #[test]
+    fn test_stakers_in_range_uses_each_deposit() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let clock = MockClock::new(start);
+        let mut contract =
+            Contract::new_with_clock(1_000_000, Duration::days(7), Box::new(clock.clone()));
+        contract.stake(String::from("Alice"), 1_000);
+        clock.advance(Duration::days(4));
+        contract.stake(String::from("Alice"), 1_000);
+
+        assert_eq!(
+            contract.stakers_in_range(start, start),
+            vec![(String::from("Alice"), 1_000)]
+        );
+        assert_eq!(
+            contract.stakers_in_range(start + Duration::days(4), start + Duration::days(4)),
+            vec![(String::from("Alice"), 1_000)]
+        );
+        assert_eq!(
+            contract.stakers_in_range(start + Duration::days(2), start + Duration::days(2)),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_merge_respects_pool_cap() {
+        let mut contract = Contract::new(1_000);
+        contract.max_total_stake = Some(10);
+        contract.stake(String::from("Alice"), 10);
+        let mut other = Contract::new(500);
+        other.stake(String::from("Bob"), 1_000);
+
+        assert_eq!(contract.merge(other), Err(StakingError::CapExceeded));
+        assert_eq!(contract.total_staked(), 10);
+        assert_eq!(contract.total_coins, 1_000);
+    }
+
+    #[test]
+    fn test_unstake_zero_rejected() {
+        let mut contract = Contract::new(1_000_000);
+        contract.stake(String::from("Alice"), 5_000);
+        let events = contract.events().len();
+
+        assert_eq!(contract.unstake("Alice", 0), Err(StakingError::ZeroAmount));
+        assert_eq!(contract.events().len(), events);
+    }
+
+    #[test]
+    fn test_huge_durations_never_expire() {
+        let mut contract = Contract::new_with_owner(1_000_000, String::from("admin"));
+        contract.staking_duration = Duration::milliseconds(i64::MAX);
+        contract.stake_cooldown = Duration::milliseconds(i64::MAX);
+        contract.grace_period = Duration::milliseconds(i64::MAX);
+
+        assert!(contract.is_staking_open());
+        assert_eq!(contract.deadline(), DateTime::<Utc>::MAX_UTC);
+        contract.stake(String::from("Alice"), 5_000);
+        assert_eq!(
+            contract.try_stake(String::from("Alice"), 5_000),
+            Err(StakingError::CooldownActive)
+        );
+        contract.extend_window("admin", Duration::days(1)).unwrap();
+        assert!(contract.remaining_window() > Duration::zero());
+        contract
+            .stake_locked(String::from("Bob"), 5_000, Duration::milliseconds(i64::MAX))
+            .unwrap();
+        assert_eq!(
+            contract.unstake("Bob", 5_000),
+            Err(StakingError::StillLocked)
+        );
+    }
+
+    #[test]
+    fn test_distribute_rewards_at_u128_max() {
+        let mut contract = Contract::new(u128::MAX);
+        contract.stake(String::from("Alice"), 1);
+        assert_eq!(
+            contract.distribute_rewards(),
+            vec![(String::from("Alice"), u128::MAX)]
+        );
+
+        let mut contract = Contract::new(1 << 127);
+        contract.stake(String::from("Alice"), 1);
+        contract.stake(String::from("Bob"), 1);
+        assert_eq!(
+            contract.distribute_rewards(),
+            vec![
+                (String::from("Alice"), 1 << 126),
+                (String::from("Bob"), 1 << 126)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_large_pool_pays_small_weights() {
+        let mut contract = Contract::new(u128::MAX);
+        contract.stake(String::from("Alice"), u64::MAX);
+        contract.stake(String::from("Bob"), 1);
+        let rewards = contract.distribute_rewards();
+        assert_eq!(rewards[1].0, "Bob");
+        assert_eq!(rewards[1].1, u128::MAX / (u64::MAX as u128 + 1));
+        assert_eq!(rewards[0].1 + rewards[1].1, u128::MAX);
+    }
+
+    #[test]
+    fn test_mul_div_rem_is_exact() {
+        assert_eq!(mul_div_rem(u128::MAX, u128::MAX, u128::MAX), (u128::MAX, 0));
+        assert_eq!(mul_div_rem(u128::MAX, 1, 3), (u128::MAX / 3, 0));
+        assert_eq!(mul_div_rem(u128::MAX, 2, 3), (u128::MAX / 3 * 2, 0));
+        assert_eq!(mul_div_rem(1 << 127, 3, 4), (3 << 125, 0));
+        assert_eq!(
+            mul_div_rem(u128::MAX, 5, 7),
+            (
+                u128::MAX / 7 * 5 + u128::MAX % 7 * 5 / 7,
+                u128::MAX % 7 * 5 % 7
+            )
+        );
+    }
+
+    #[test]
+    fn test_lifetime_distributed_counts_each_epoch_once() {
+        let mut contract = Contract::new_with_owner(1_000, String::from("admin"));
+        contract.stake(String::from("Alice"), 5_000);
+        contract.distribute_rewards();
+        contract.distribute_rewards();
+        contract.advance_epoch("admin", 500).unwrap();
+        assert_eq!(contract.lifetime_distributed(), 1_000);
+
+        contract.finalize("admin").unwrap();
+        for _ in 0..3 {
+            contract.distribute_rewards();
+        }
+        assert_eq!(contract.lifetime_distributed(), 1_500);
+    }
+
+    #[test]
+    fn test_pool_and_window_operations_require_owner() {
+        let mut contract = Contract::new_with_owner(1_000, String::from("admin"));
+        contract.stake(String::from("Alice"), 5);
+
+        assert_eq!(
+            contract.advance_epoch("Alice", 0),
+            Err(StakingError::Unauthorized)
+        );
+        assert_eq!(
+            contract.sweep_dust("Alice", u64::MAX),
+            Err(StakingError::Unauthorized)
+        );
+        assert_eq!(
+            contract.extend_window("Alice", Duration::days(1)),
+            Err(StakingError::Unauthorized)
+        );
+        assert_eq!(
+            contract.distribute_fraction("Alice", 10_000),
+            Err(StakingError::Unauthorized)
+        );
+        assert_eq!(contract.epoch, 0);
+        assert_eq!(contract.total_coins, 1_000);
+        assert_eq!(contract.get_stake("Alice"), Some(5));
+
+        let mut unowned = Contract::new(1_000);
+        assert_eq!(
+            unowned.advance_epoch("", 0),
+            Err(StakingError::Unauthorized)
+        );
+    }
+
+    #[test]
+    fn test_merge_carries_per_user_state() {
+        let mut contract = Contract::new(1_000);
+        contract.stake(String::from("Alice"), 5_000);
+        contract.fee_pool = 7;
+        let mut other = Contract::new_with_owner(600, String::from("admin"));
+        other
+            .stake_with_referral(String::from("Bob"), 2_000, Some(String::from("Alice")))
+            .unwrap();
+        other
+            .delegate_stake(String::from("Dana"), String::from("Carol"), 1_000)
+            .unwrap();
+        other.accrue_rewards();
+        other.freeze("admin", String::from("Bob")).unwrap();
+        other.fee_pool = 3;
+
+        contract.merge(other).unwrap();
+
+        assert!(contract.is_frozen("Bob"));
+        assert_eq!(contract.unstake("Bob", 2_000), Err(StakingError::Frozen));
+        assert_eq!(contract.unclaimed.get("Bob"), Some(&400));
+        assert_eq!(contract.claim("Carol"), Ok(200));
+        assert_eq!(contract.fee_pool, 10);
+        assert_eq!(contract.stake_history("Bob").map(<[_]>::len), Some(1));
+        assert_eq!(contract.delegator_of("Carol"), Some("Dana"));
+        assert_eq!(contract.referral_count("Alice"), 1);
+    }
+
+    #[test]
+    fn test_stake_history_follows_every_debit() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let clock = MockClock::new(start);
+        let mut contract =
+            Contract::new_with_clock(1_000_000, Duration::days(7), Box::new(clock.clone()));
+        contract.owner = String::from("admin");
+        contract.stake(String::from("Alice"), 5_000);
+        clock.advance(Duration::days(1));
+        contract.stake(String::from("Alice"), 3_000);
+
+        contract.unstake("Alice", 1_000).unwrap();
+        assert_eq!(
+            contract.stake_history("Alice"),
+            Some(&[(5_000, start), (2_000, start + Duration::days(1))][..])
+        );
+        contract
+            .transfer_stake("Alice", String::from("Bob"), 4_000)
+            .unwrap();
+        assert_eq!(contract.stake_history("Alice"), Some(&[(3_000, start)][..]));
+        assert_eq!(
+            contract.stake_history("Bob"),
+            Some(&[(2_000, start), (2_000, start + Duration::days(1))][..])
+        );
+        contract.slash("admin", "Bob", 500).unwrap();
+        assert_eq!(
+            contract.stake_history("Bob"),
+            Some(&[(2_000, start), (1_500, start + Duration::days(1))][..])
+        );
+        for user in ["Alice", "Bob"] {
+            assert_eq!(
+                contract
+                    .stake_history(user)
+                    .unwrap()
+                    .iter()
+                    .map(|(amount, _)| amount)
+                    .sum::<u64>(),
+                contract.get_stake(user).unwrap()
+            );
+        }
+
+        contract.remove_staker("admin", "Alice").unwrap();
+        assert_eq!(contract.stake_history("Alice"), None);
+        contract.sweep_dust("admin", u64::MAX).unwrap();
+        assert_eq!(contract.stake_history("Bob"), None);
+    }
+
+    #[test]
+    fn test_stakers_in_range_ignores_withdrawn_deposits() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let clock = MockClock::new(start);
+        let mut contract =
+            Contract::new_with_clock(1_000_000, Duration::days(7), Box::new(clock.clone()));
+        contract.stake(String::from("A"), 1_000);
+        contract.unstake("A", 1_000).unwrap();
+        clock.advance(Duration::hours(1));
+        contract.stake(String::from("A"), 10);
+
+        assert_eq!(
+            contract.stakers_in_range(start, start + Duration::days(1)),
+            vec![(String::from("A"), 10)]
+        );
+        assert_eq!(contract.get_stake("A"), Some(10));
+    }
+
+    #[test]
+    fn test_batch_stake_applies_cooldown_within_batch() {
+        let mut contract = Contract::new(1_000);
+        contract.stake_cooldown = Duration::hours(1);
+        assert_eq!(
+            contract.batch_stake(vec![(String::from("A"), 5), (String::from("A"), 5)]),
+            Err(StakingError::CooldownActive)
+        );
+        assert_eq!(contract.get_stake("A"), None);
+
+        contract.stake_cooldown = Duration::zero();
+        contract
+            .batch_stake(vec![(String::from("A"), 5), (String::from("A"), 5)])
+            .unwrap();
+        assert_eq!(contract.get_stake("A"), Some(10));
+    }
+
+    #[test]
+    fn test_admin_stake_changes_are_logged() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let clock = MockClock::new(start);
+        let mut contract =
+            Contract::new_with_clock(1_000, Duration::days(7), Box::new(clock.clone()));
+        contract.owner = String::from("admin");
+        contract.stake(String::from("Alice"), 5_000);
+        contract.stake(String::from("Bob"), 50);
+        contract.stake(String::from("Carol"), 2_000);
+        let mut other = Contract::new(0);
+        other.stake(String::from("Dave"), 700);
+
+        contract.slash("admin", "Alice", 1_000).unwrap();
+        contract.sweep_dust("admin", 100).unwrap();
+        contract.remove_staker("admin", "Carol").unwrap();
+        contract.merge(other).unwrap();
+        contract
+            .transfer_stake("Alice", String::from("Dave"), 300)
+            .unwrap();
+
+        let unstaked = |user: &str, amount| Event::Unstaked {
+            user: String::from(user),
+            amount,
+            at: start,
+        };
+        let staked = |user: &str, amount| Event::Staked {
+            user: String::from(user),
+            amount,
+            at: start,
+        };
+        assert_eq!(
+            contract.events()[3..],
+            [
+                unstaked("Alice", 1_000),
+                unstaked("Bob", 50),
+                unstaked("Carol", 2_000),
+                staked("Dave", 700),
+                unstaked("Alice", 300),
+                staked("Dave", 300),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_restake_respects_cap_and_whitelist() {
+        let mut contract = Contract::new(1_000);
+        contract.stake(String::from("Alice"), 100);
+        contract.max_total_stake = Some(400);
+        contract.accrue_rewards();
+
+        assert_eq!(contract.restake("Alice"), Ok(300));
+        assert_eq!(contract.total_staked(), 400);
+        assert_eq!(contract.unclaimed.get("Alice"), Some(&700));
+        assert_eq!(contract.restake("Alice"), Err(StakingError::CapExceeded));
+        assert_eq!(contract.unclaimed.get("Alice"), Some(&700));
+
+        contract.max_total_stake = None;
+        contract.allow(String::from("Bob"));
+        assert_eq!(contract.restake("Alice"), Err(StakingError::NotWhitelisted));
+        assert_eq!(contract.unclaimed.get("Alice"), Some(&700));
+    }
+
+    #[test]
+    fn test_from_roster_json_rejects_duplicate_users() {
+        assert_eq!(
+            Contract::from_roster_json(1_000, r#"{ "A": 5, "A": 7 }"#),
+            Err(StakingError::InvalidRoster(String::from(
+                "duplicate user `A`"
+            )))
+        );
+        assert_eq!(
+            Contract::from_roster_json(1_000, r#"{ "Alice": 5, " Alice ": 7 }"#),
+            Err(StakingError::InvalidRoster(String::from(
+                "duplicate user ` Alice `"
+            )))
+        );
+    }
+}
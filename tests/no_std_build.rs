@@ -0,0 +1,13 @@
+use std::process::Command;
+
+#[test]
+fn test_core_builds_without_std() {
+    let manifest_dir = env!("CARGO_MANIFEST_DIR");
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "--lib", "--no-default-features", "--target-dir"])
+        .arg(format!("{}/target/no_std-check", manifest_dir))
+        .current_dir(manifest_dir)
+        .status()
+        .expect("failed to run cargo");
+    assert!(status.success());
+}
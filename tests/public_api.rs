@@ -0,0 +1,26 @@
+use chrono::{Duration, TimeZone, Utc};
+use staking_together::{Contract, MockClock, StakingError};
+
+#[test]
+fn test_stake_and_distribute_through_public_api() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+    let mut contract =
+        Contract::new_with_clock(1_000_000, Duration::days(7), Box::new(clock.clone()));
+
+    contract.stake(String::from("Alice"), 5_000);
+    contract.stake(String::from("Bob"), 20_000);
+
+    assert_eq!(
+        contract.distribute_rewards(),
+        vec![
+            (String::from("Alice"), 200_000),
+            (String::from("Bob"), 800_000)
+        ]
+    );
+
+    clock.advance(Duration::days(8));
+    assert_eq!(
+        contract.try_stake(String::from("Carol"), 1_000),
+        Err(StakingError::WindowClosed)
+    );
+}